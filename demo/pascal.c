@@ -41,24 +41,13 @@ void main()
         switch (res) {
             case ZOWSKI_RESULT_OK:
                 {
-                    zowski_token_t token_typ = zowski_lexer_get_token_type(lex);
-                    switch (token_typ)
-                    {
-                        case ZOWSKI_TOKEN_TYP_NEWLINE:
-                        case ZOWSKI_TOKEN_TYP_WHITESPACE:
-                        case ZOWSKI_TOKEN_TYP_LINECOMMENT:
-                        case ZOWSKI_TOKEN_TYP_COMMENT:
-                            // Ignore some token types.
-                            break;
-                        default:
-                            // display token:
-                            zowski_lexer_token_copy_text(lex, buf, TOKEN_TEXT_BUFSIZE);
-                            int tok_start = zowski_lexer_get_token_start(lex);
-                            int tok_end = zowski_lexer_get_token_end(lex);
-                            const char* tok_name = zowski_lexer_token_type_name(lex);
+                    // display token, whitespace and comments are skipped by the scanner:
+                    zowski_lexer_token_copy_text(lex, buf, TOKEN_TEXT_BUFSIZE);
+                    int tok_start = zowski_lexer_get_token_start(lex);
+                    int tok_end = zowski_lexer_get_token_end(lex);
+                    const char* tok_name = zowski_lexer_token_type_name(lex);
 
-                            printf("Token span=%d-%d typ=%s: [%s]\n", tok_start, tok_end, tok_name, buf);
-                    }
+                    printf("Token span=%d-%d typ=%s: [%s]\n", tok_start, tok_end, tok_name, buf);
                 }
                 break;
             case ZOWSKI_RESULT_ERROR:
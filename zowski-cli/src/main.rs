@@ -9,27 +9,56 @@ fn convert() {
         .author("Windel Bouwman")
         .arg(clap::Arg::with_name("filename").required(true))
         .arg(clap::Arg::with_name("basename").required(true))
+        .arg(
+            clap::Arg::with_name("allow-shadowed")
+                .long("allow-shadowed")
+                .help("Generate a scanner, even if some tokens can never be produced"),
+        )
         .get_matches();
 
     let filename = matches.value_of("filename").unwrap();
     let basename = matches.value_of("basename").unwrap();
+    let allow_shadowed = matches.is_present("allow-shadowed");
 
     match zowski::read_spec(filename) {
         Ok(specs) => {
-            let ev = spec_to_expression_vector(specs);
-            let dfa = zowski::compile(ev);
-            zowski::write_c_code(&dfa, basename).unwrap();
+            let ev = zowski::ExpressionVector::from_specs(specs);
+            let dfa = match zowski::compile(ev) {
+                Ok(dfa) => dfa,
+                Err(err) => {
+                    eprintln!("error: {}", err);
+                    std::process::exit(1);
+                }
+            };
+            for warning in &dfa.warnings {
+                eprintln!("warning: {}", warning);
+            }
+            // Overlaps which are not resolved by priority depend on the
+            // order of the spec, which is easily changed by accident.
+            for overlap in zowski::overlapping_tokens(&dfa) {
+                if overlap.by_priority {
+                    eprintln!("note: {}", overlap);
+                } else {
+                    eprintln!("warning: {}", overlap);
+                }
+            }
+            let shadowed = zowski::shadowed_tokens(&dfa);
+            if allow_shadowed {
+                for token in &shadowed {
+                    eprintln!("warning: {}", token);
+                }
+            } else if !shadowed.is_empty() {
+                for token in &shadowed {
+                    eprintln!("error: {}", token);
+                }
+                eprintln!("Use --allow-shadowed to generate the scanner anyway");
+                std::process::exit(1);
+            }
+            zowski::write_c_code(&zowski::minimize(dfa), basename).unwrap();
         }
         Err(err) => {
-            println!("Error: {:?}", err);
+            eprintln!("{}", err);
+            std::process::exit(1);
         }
     }
 }
-
-fn spec_to_expression_vector(specs: Vec<zowski::TokenSpec>) -> zowski::ExpressionVector {
-    let mut ev = vec![];
-    for spec in specs {
-        ev.push((spec.name, spec.pattern));
-    }
-    zowski::ExpressionVector::new(ev)
-}
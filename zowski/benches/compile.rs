@@ -0,0 +1,35 @@
+//! Measure how long it takes to compile the Pascal example spec.
+//!
+//! Run with `cargo bench`.
+
+use std::time::{Duration, Instant};
+
+const SPEC: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../examples/pascal.zowski");
+const ROUNDS: u32 = 50;
+
+fn expression_vector() -> zowski::ExpressionVector {
+    zowski::ExpressionVector::from_specs(zowski::read_spec(SPEC).unwrap())
+}
+
+fn main() {
+    let ev = expression_vector();
+    let mut fastest = Duration::MAX;
+    let mut total = Duration::ZERO;
+    let mut states = 0;
+    for _ in 0..ROUNDS {
+        let ev = ev.clone();
+        let start = Instant::now();
+        let dfa = zowski::compile(ev).unwrap();
+        let elapsed = start.elapsed();
+        fastest = fastest.min(elapsed);
+        total += elapsed;
+        states = dfa.transitions.len();
+    }
+    eprintln!(
+        "compile pascal.zowski: {} states, {:?} on average, {:?} fastest, over {} rounds",
+        states,
+        total / ROUNDS,
+        fastest,
+        ROUNDS
+    );
+}
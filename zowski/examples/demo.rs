@@ -14,5 +14,5 @@ fn main() {
     // scan(r, "AAA");
 
     let ev = zowski::ExpressionVector::new(vec![("TEST".to_owned(), r)]);
-    let _dfa = zowski::compile(ev);
+    let _dfa = zowski::compile(ev).unwrap();
 }
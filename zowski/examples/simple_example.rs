@@ -10,8 +10,8 @@ fn main() {
     other = .
 
     */
-    let digit = Regex::from("[0-9]");
-    let letter = Regex::from("[A-Za-z_]");
+    let digit = Regex::parse_or_panic("[0-9]");
+    let letter = Regex::parse_or_panic("[A-Za-z_]");
     let identifier = letter.clone() + (letter | digit.clone()).kleene();
     let number = digit.one_or_more();
     let operator = Regex::symbol('-')
@@ -24,6 +24,6 @@ fn main() {
         ("NUM".to_owned(), number),
         ("OP".to_owned(), operator),
     ]);
-    let dfa = zowski::compile(ev);
+    let dfa = zowski::compile(ev).unwrap();
     zowski::write_dot(dfa).unwrap();
 }
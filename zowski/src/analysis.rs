@@ -0,0 +1,296 @@
+//! Analysis of compiled scanners, to inform users about their token spec.
+
+use crate::dfa::Dfa;
+use crate::expression::CharSet;
+use std::collections::{HashMap, HashSet};
+
+/// Two tokens which can match the same text.
+#[derive(Debug, PartialEq)]
+pub struct Overlap {
+    /// The token which is reported when both match.
+    pub winner: String,
+    pub other: String,
+
+    /// A shortest text matched by both tokens.
+    pub witness: String,
+
+    /// Whether the winner has a higher priority, instead of
+    /// being declared first.
+    pub by_priority: bool,
+}
+
+impl std::fmt::Display for Overlap {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "`{}` and `{}` both match `{}`; `{}` wins {}",
+            self.other,
+            self.winner,
+            self.witness.escape_debug(),
+            self.winner,
+            if self.by_priority {
+                "by priority"
+            } else {
+                "as it is declared first"
+            }
+        )
+    }
+}
+
+/// A token which is never produced by the scanner.
+#[derive(Debug, PartialEq)]
+pub struct Shadowed {
+    pub token: String,
+
+    /// The tokens which win instead, which is empty when
+    /// the token does not match any text.
+    pub by: Vec<String>,
+}
+
+impl std::fmt::Display for Shadowed {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.by.is_empty() {
+            write!(
+                f,
+                "`{}` is never produced, as it does not match any text",
+                self.token
+            )
+        } else {
+            let by: Vec<String> = self.by.iter().map(|t| format!("`{}`", t)).collect();
+            write!(
+                f,
+                "`{}` is never produced, as it is shadowed by {}",
+                self.token,
+                by.join(", ")
+            )
+        }
+    }
+}
+
+/// Find the tokens which do not win in any accepting state.
+///
+/// Such a token only matches text which is also matched by tokens
+/// with a higher priority, or which are declared earlier. The empty
+/// string is not taken into account, as empty tokens are never produced.
+pub fn shadowed_tokens(dfa: &Dfa) -> Vec<Shadowed> {
+    let states: Vec<usize> = shortest_witnesses(dfa)
+        .into_iter()
+        .map(|(state, _)| state)
+        .filter(|state| dfa.accepting.contains_key(state))
+        .collect();
+
+    let mut shadowed = vec![];
+    for token in &dfa.token_types {
+        let mut by: Vec<String> = vec![];
+        let mut wins = false;
+        for state in &states {
+            let tokens = &dfa.accepting[state];
+            if tokens[0] == *token {
+                wins = true;
+                break;
+            } else if tokens.contains(token) && !by.contains(&tokens[0]) {
+                by.push(tokens[0].clone());
+            }
+        }
+        if !wins {
+            shadowed.push(Shadowed {
+                token: token.clone(),
+                by,
+            });
+        }
+    }
+    shadowed
+}
+
+/// Find all pairs of tokens which can match the same text.
+///
+/// The pairs are ordered by the length of their witness.
+pub fn overlapping_tokens(dfa: &Dfa) -> Vec<Overlap> {
+    let priority = |name: &str| {
+        dfa.priorities
+            .iter()
+            .find(|(n, _)| n == name)
+            .map_or(0, |(_, priority)| *priority)
+    };
+
+    let mut overlaps = vec![];
+    let mut found: HashSet<(&str, &str)> = HashSet::new();
+    for (state, witness) in shortest_witnesses(dfa) {
+        if let Some(tokens) = dfa.accepting.get(&state) {
+            for (i, winner) in tokens.iter().enumerate() {
+                for other in &tokens[i + 1..] {
+                    if found.insert((winner, other)) {
+                        overlaps.push(Overlap {
+                            winner: winner.clone(),
+                            other: other.clone(),
+                            witness: witness.clone(),
+                            by_priority: priority(winner) > priority(other),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    overlaps
+}
+
+/// Determine a shortest non-empty text leading to each reachable state,
+/// using a breadth first search from the start state.
+///
+/// States are returned in the order of discovery, so shortest first.
+/// The start state is only included when it can be reached again.
+fn shortest_witnesses(dfa: &Dfa) -> Vec<(usize, String)> {
+    let transitions: HashMap<usize, &Vec<(CharSet, usize)>> =
+        dfa.transitions.iter().map(|(s, t)| (*s, t)).collect();
+
+    // The list of witnesses doubles as the queue of the search,
+    // starting with the empty text, which is removed afterwards.
+    let mut witnesses = vec![(0, String::new())];
+    let mut visited: HashSet<usize> = HashSet::new();
+    let mut next = 0;
+    while next < witnesses.len() {
+        let (state, witness) = witnesses[next].clone();
+        next += 1;
+        for (char_class, next_state) in transitions[&state].iter() {
+            if *next_state != dfa.error_state && visited.insert(*next_state) {
+                let mut next_witness = witness.clone();
+                next_witness.push(readable_char(char_class));
+                witnesses.push((*next_state, next_witness));
+            }
+        }
+    }
+    witnesses.remove(0);
+    witnesses
+}
+
+/// Pick a character from the set, preferring printable characters.
+fn readable_char(set: &CharSet) -> char {
+    let preferred = [
+        CharSet::new2('a', 'z'),
+        CharSet::new2('!', '~'),
+        CharSet::new(' '),
+        CharSet::new2('\u{A1}', std::char::MAX),
+    ];
+    preferred
+        .iter()
+        .map(|p| set.intersection(p))
+        .find(|s| !s.is_empty())
+        .map_or_else(|| set.first(), |s| s.first())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{overlapping_tokens, shadowed_tokens, Overlap, Shadowed};
+    use crate::{compile, ExpressionVector, Regex};
+
+    #[test]
+    fn overlapping_keyword() {
+        let ev = ExpressionVector::new(vec![
+            ("NUMBER".to_owned(), Regex::parse_or_panic("[0-9]+")),
+            ("IDENTIFIER".to_owned(), Regex::parse_or_panic("[A-Za-z]+")),
+            ("BEGIN".to_owned(), Regex::parse_or_panic("(?i:begin)")),
+            ("B".to_owned(), Regex::parse_or_panic("[bB]")),
+        ])
+        .with_priorities(vec![("BEGIN".to_owned(), 1)]);
+        let overlaps = overlapping_tokens(&compile(ev).unwrap());
+        assert_eq!(
+            overlaps,
+            vec![
+                Overlap {
+                    winner: "IDENTIFIER".to_owned(),
+                    other: "B".to_owned(),
+                    witness: "b".to_owned(),
+                    by_priority: false,
+                },
+                Overlap {
+                    winner: "BEGIN".to_owned(),
+                    other: "IDENTIFIER".to_owned(),
+                    witness: "begin".to_owned(),
+                    by_priority: true,
+                },
+            ]
+        );
+        assert_eq!(
+            overlaps[1].to_string(),
+            "`IDENTIFIER` and `BEGIN` both match `begin`; `BEGIN` wins by priority"
+        );
+    }
+
+    #[test]
+    fn no_overlap() {
+        let ev = ExpressionVector::new(vec![
+            ("NUMBER".to_owned(), Regex::parse_or_panic("[0-9]+")),
+            ("SPACE".to_owned(), Regex::parse_or_panic("\\s+")),
+        ]);
+        assert!(overlapping_tokens(&compile(ev).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn shadowed_keyword() {
+        let ev = ExpressionVector::new(vec![
+            ("ID".to_owned(), Regex::parse_or_panic("[a-z]+")),
+            ("IF".to_owned(), Regex::parse_or_panic("if")),
+            ("LETTER".to_owned(), Regex::parse_or_panic("[a-z]")),
+            ("NOTHING".to_owned(), Regex::parse_or_panic("a&b")),
+            ("NUMBER".to_owned(), Regex::parse_or_panic("[0-9]+")),
+        ]);
+        let shadowed = shadowed_tokens(&compile(ev.clone()).unwrap());
+        assert_eq!(
+            shadowed,
+            vec![
+                Shadowed {
+                    token: "IF".to_owned(),
+                    by: vec!["ID".to_owned()],
+                },
+                Shadowed {
+                    token: "LETTER".to_owned(),
+                    by: vec!["ID".to_owned()],
+                },
+                Shadowed {
+                    token: "NOTHING".to_owned(),
+                    by: vec![],
+                },
+            ]
+        );
+        assert_eq!(
+            shadowed[0].to_string(),
+            "`IF` is never produced, as it is shadowed by `ID`"
+        );
+
+        // A higher priority lets the keyword win.
+        let ev = ev.with_priorities(vec![("IF".to_owned(), 1)]);
+        let shadowed = shadowed_tokens(&compile(ev).unwrap());
+        let tokens: Vec<&str> = shadowed.iter().map(|s| s.token.as_str()).collect();
+        assert_eq!(tokens, vec!["LETTER", "NOTHING"]);
+    }
+
+    #[test]
+    fn empty_string_ignored() {
+        let ev = ExpressionVector::new(vec![
+            ("SPACE".to_owned(), Regex::parse_or_panic("[ ]*")),
+            ("EMPTY".to_owned(), Regex::parse_or_panic("\"\"")),
+            ("AB".to_owned(), Regex::parse_or_panic("(ab)*")),
+        ]);
+        let dfa = compile(ev).unwrap();
+        assert!(overlapping_tokens(&dfa).is_empty());
+        let shadowed = shadowed_tokens(&dfa);
+        assert_eq!(
+            shadowed,
+            vec![Shadowed {
+                token: "EMPTY".to_owned(),
+                by: vec![],
+            }]
+        );
+    }
+
+    #[test]
+    fn printable_witness() {
+        let ev = ExpressionVector::new(vec![
+            ("ANY".to_owned(), Regex::parse_or_panic(".")),
+            ("OTHER".to_owned(), Regex::parse_or_panic("[^ab]")),
+        ]);
+        let overlaps = overlapping_tokens(&compile(ev).unwrap());
+        assert_eq!(overlaps.len(), 1);
+        assert_eq!(overlaps[0].witness, "c");
+    }
+}
@@ -0,0 +1,338 @@
+//! Hash-consed storage of regexes, used while compiling.
+//!
+//! Every distinct regex is stored once in an arena, and referred to by
+//! its id. Sub-terms are shared between regexes, and comparing or hashing
+//! a regex only compares or hashes its id.
+//!
+//! Regexes are kept in the same normal form as the ones of `Regex`,
+//! except that operands of alternations and intersections are sorted by id.
+//!
+//! As the same sub-terms show up in many states, their properties are
+//! cached: nullability when a regex is added, and character classes and
+//! derivatives when they are first needed.
+
+use crate::expression::{product_intersections, sigma, CharSet, Regex};
+use crate::normal::{self, Kind, Terms};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// A reference to a regex in an arena.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RegexId(u32);
+
+/// The empty string, which has the lowest id, such that it sorts first.
+const EPSILON: RegexId = RegexId(0);
+const NULL: RegexId = RegexId(1);
+const SIGMA: RegexId = RegexId(2);
+
+/// Any string, like `.*`
+const UNIVERSAL: RegexId = RegexId(3);
+
+type Node = normal::Node<RegexId>;
+
+pub struct Arena {
+    nodes: Vec<Node>,
+    ids: HashMap<Node, RegexId>,
+
+    /// Whether each regex is nullable, determined when it is added.
+    nullable: Vec<bool>,
+
+    /// Character classes of regexes.
+    classes: HashMap<RegexId, Rc<Vec<CharSet>>>,
+
+    /// Derivatives, by regex and the index of the character class of
+    /// the regex, as the derivative is the same for all its characters.
+    derivatives: HashMap<(RegexId, usize), RegexId>,
+}
+
+impl Arena {
+    pub fn new() -> Self {
+        let mut arena = Arena {
+            nodes: vec![],
+            ids: HashMap::new(),
+            nullable: vec![],
+            classes: HashMap::new(),
+            derivatives: HashMap::new(),
+        };
+        arena.intern(Node::Epsilon);
+        arena.intern(Node::SymbolSet(CharSet::empty()));
+        arena.intern(Node::SymbolSet(sigma()));
+        arena.intern(Node::Kleene(SIGMA));
+        arena
+    }
+
+    /// Add a regex to the arena, and retrieve its id.
+    pub fn insert(&mut self, regex: &Regex) -> RegexId {
+        match regex {
+            Regex::Epsilon => EPSILON,
+            Regex::SymbolSet(s) => self.intern(Node::SymbolSet(s.clone())),
+            Regex::Kleene(r) => {
+                let r = self.insert(r);
+                self.kleene(r)
+            }
+            Regex::Alternation { left, right } => {
+                let (left, right) = (self.insert(left), self.insert(right));
+                self.alternation(left, right)
+            }
+            Regex::Concatenation { left, right } => {
+                let (left, right) = (self.insert(left), self.insert(right));
+                self.concatenate(left, right)
+            }
+            Regex::LogicalAnd { left, right } => {
+                let (left, right) = (self.insert(left), self.insert(right));
+                self.logical_and(left, right)
+            }
+            Regex::LogicalNot(r) => {
+                let r = self.insert(r);
+                self.logical_not(r)
+            }
+        }
+    }
+
+    fn intern(&mut self, node: Node) -> RegexId {
+        if let Some(id) = self.ids.get(&node) {
+            return *id;
+        }
+
+        let nullable = match node {
+            Node::Epsilon | Node::Kleene(_) => true,
+            Node::SymbolSet(_) => false,
+            Node::Alternation(left, right) => self.is_nullable(left) || self.is_nullable(right),
+            Node::Concatenation(left, right) | Node::LogicalAnd(left, right) => {
+                self.is_nullable(left) && self.is_nullable(right)
+            }
+            Node::LogicalNot(r) => !self.is_nullable(r),
+        };
+        let id = RegexId(self.nodes.len() as u32);
+        self.nodes.push(node.clone());
+        self.nullable.push(nullable);
+        self.ids.insert(node, id);
+        id
+    }
+
+    fn node(&self, id: RegexId) -> &Node {
+        &self.nodes[id.0 as usize]
+    }
+
+    /// Determine if the regex can be reduced to the empty string.
+    pub fn is_nullable(&self, id: RegexId) -> bool {
+        self.nullable[id.0 as usize]
+    }
+
+    /// Test if the regex is the null set.
+    pub fn is_null(&self, id: RegexId) -> bool {
+        id == NULL
+    }
+
+    /// Construct the derivative of a regex with respect to a character.
+    pub fn derivative(&mut self, id: RegexId, c: char) -> RegexId {
+        // Symbols are cheaper to derive than to look up.
+        match *self.node(id) {
+            Node::Epsilon => return NULL,
+            Node::SymbolSet(ref s) => return if s.contains(c) { EPSILON } else { NULL },
+            _ => {}
+        }
+
+        let class = self
+            .character_classes(id)
+            .iter()
+            .position(|class| class.contains(c))
+            .expect("Character classes cover all characters");
+        if let Some(derivative) = self.derivatives.get(&(id, class)) {
+            return *derivative;
+        }
+
+        let derivative = match *self.node(id) {
+            Node::Epsilon | Node::SymbolSet(_) => unreachable!(),
+            Node::Kleene(r) => {
+                let d = self.derivative(r, c);
+                self.concatenate(d, id)
+            }
+            Node::Alternation(left, right) => {
+                let (left, right) = (self.derivative(left, c), self.derivative(right, c));
+                self.alternation(left, right)
+            }
+            Node::LogicalAnd(left, right) => {
+                let (left, right) = (self.derivative(left, c), self.derivative(right, c));
+                self.logical_and(left, right)
+            }
+            Node::Concatenation(left, right) => {
+                let d = self.derivative(left, c);
+                let d = self.concatenate(d, right);
+                if self.is_nullable(left) {
+                    let d_right = self.derivative(right, c);
+                    self.alternation(d, d_right)
+                } else {
+                    d
+                }
+            }
+            Node::LogicalNot(r) => {
+                let d = self.derivative(r, c);
+                self.logical_not(d)
+            }
+        };
+        self.derivatives.insert((id, class), derivative);
+        derivative
+    }
+
+    /// Get the sets of characters on which the derivative may differ.
+    ///
+    /// This is the same as `Regex::character_classes`.
+    pub fn character_classes(&mut self, id: RegexId) -> Rc<Vec<CharSet>> {
+        if let Some(classes) = self.classes.get(&id) {
+            return classes.clone();
+        }
+
+        let classes = match *self.node(id) {
+            Node::Epsilon => vec![sigma()],
+            Node::SymbolSet(ref s) => {
+                if s.is_empty() {
+                    vec![sigma()]
+                } else {
+                    vec![s.clone(), sigma().difference(s)]
+                }
+            }
+            Node::Kleene(r) | Node::LogicalNot(r) => self.character_classes(r).to_vec(),
+            Node::Alternation(left, right) | Node::LogicalAnd(left, right) => {
+                product_intersections(
+                    self.character_classes(left).to_vec(),
+                    self.character_classes(right).to_vec(),
+                )
+            }
+            Node::Concatenation(left, right) => {
+                if self.is_nullable(left) {
+                    product_intersections(
+                        self.character_classes(left).to_vec(),
+                        self.character_classes(right).to_vec(),
+                    )
+                } else {
+                    self.character_classes(left).to_vec()
+                }
+            }
+        };
+        let classes = Rc::new(classes);
+        self.classes.insert(id, classes.clone());
+        classes
+    }
+
+    fn kleene(&mut self, id: RegexId) -> RegexId {
+        normal::kleene(self, id)
+    }
+
+    fn logical_not(&mut self, id: RegexId) -> RegexId {
+        normal::logical_not(self, id)
+    }
+
+    fn concatenate(&mut self, left: RegexId, right: RegexId) -> RegexId {
+        normal::concatenate(self, left, right)
+    }
+
+    fn alternation(&mut self, left: RegexId, right: RegexId) -> RegexId {
+        normal::alternation(self, left, right)
+    }
+
+    fn logical_and(&mut self, left: RegexId, right: RegexId) -> RegexId {
+        normal::logical_and(self, left, right)
+    }
+}
+
+impl Terms for Arena {
+    type Term = RegexId;
+
+    fn make(&mut self, node: Node) -> RegexId {
+        self.intern(node)
+    }
+
+    fn take(&self, id: RegexId) -> Node {
+        self.node(id).clone()
+    }
+
+    fn kind(&self, id: &RegexId) -> Kind {
+        match self.node(*id) {
+            Node::Epsilon => Kind::Epsilon,
+            Node::SymbolSet(_) => Kind::SymbolSet,
+            Node::Kleene(_) => Kind::Kleene,
+            Node::Alternation(..) => Kind::Alternation,
+            Node::Concatenation(..) => Kind::Concatenation,
+            Node::LogicalAnd(..) => Kind::LogicalAnd,
+            Node::LogicalNot(_) => Kind::LogicalNot,
+        }
+    }
+
+    fn symbols<'t>(&'t self, id: &'t RegexId) -> Option<&'t CharSet> {
+        match self.node(*id) {
+            Node::SymbolSet(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn is_nullable(&self, id: &RegexId) -> bool {
+        Arena::is_nullable(self, *id)
+    }
+
+    fn is_universal(&self, id: &RegexId) -> bool {
+        *id == UNIVERSAL
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Arena;
+    use crate::expression::Regex;
+
+    #[test]
+    fn shared_terms() {
+        let mut arena = Arena::new();
+        let a = arena.insert(&Regex::parse_or_panic("(ab|cd)*"));
+        let b = arena.insert(&Regex::parse_or_panic("(cd|ab)*"));
+        assert_eq!(a, b);
+        let c = arena.insert(&Regex::parse_or_panic("(cd|ab)+"));
+        assert_ne!(a, c);
+        assert_eq!(arena.derivative(c, 'c'), arena.derivative(a, 'c'));
+    }
+
+    #[test]
+    fn cached_derivatives() {
+        let mut arena = Arena::new();
+        let id = arena.insert(&Regex::parse_or_panic("[a-z]+[0-9]"));
+        let derivative = arena.derivative(id, 'a');
+        let cached = arena.derivatives.len();
+
+        // Any other letter is in the same character class:
+        assert_eq!(arena.derivative(id, 'q'), derivative);
+        assert_eq!(arena.derivatives.len(), cached);
+        assert_ne!(arena.derivative(id, '0'), derivative);
+    }
+
+    #[test]
+    fn same_as_regex() {
+        let patterns = [
+            "[a-z]+&!(if)",
+            "/\\*!(.*\\*/.*)\\*/",
+            "(a|b)*abb",
+            "a{2,4}|b?c",
+            "!(a*)|\"\"",
+        ];
+        let texts = ["", "if", "iff", "/* a */", "/* */ */", "babb", "aaa", "c"];
+        let mut arena = Arena::new();
+        for pattern in &patterns {
+            let regex = Regex::parse_or_panic(pattern);
+            let id = arena.insert(&regex);
+            let mut classes = arena.character_classes(id).to_vec();
+            let mut expected = regex.character_classes();
+            classes.sort();
+            expected.sort();
+            assert_eq!(classes, expected);
+            for text in &texts {
+                let derived = text.chars().fold(id, |id, c| arena.derivative(id, c));
+                assert_eq!(
+                    arena.is_nullable(derived),
+                    regex.matches(text),
+                    "Pattern {} on {:?}",
+                    pattern,
+                    text
+                );
+            }
+        }
+    }
+}
@@ -1,61 +1,125 @@
-use crate::expression::CharSet;
+use crate::arena::{Arena, RegexId};
+use crate::expression::{product_intersections, sigma, CharSet};
 use crate::vector::ExpressionVector;
 use std::collections::HashMap;
 
+#[derive(Clone)]
 pub struct Dfa {
     pub token_types: Vec<String>,
     pub transitions: Vec<(usize, Vec<(CharSet, usize)>)>,
     pub accepting: HashMap<usize, Vec<String>>,
     pub error_state: usize,
+
+    /// Token types which are matched, but not reported.
+    pub skipped: Vec<String>,
+
+    /// Priorities of token types, which are 0 when not given.
+    pub priorities: Vec<(String, i32)>,
+
+    /// Problems with the token specification, found while compiling.
+    pub warnings: Vec<String>,
+}
+
+/// The largest number of states a compiled state machine may have.
+const MAX_STATES: usize = 2000;
+
+/// Error while compiling an expression vector into a state machine.
+#[derive(Debug)]
+pub struct CompileError {
+    pub message: String,
+}
+
+impl std::fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
 }
 
+impl std::error::Error for CompileError {}
+
 /// Compile the given expression vector into a state machine.
 ///
 /// The state machine contains:
 /// - state transitions
 /// - a list of accepting state
 /// - an error state
-pub fn compile(start_state: ExpressionVector) -> Dfa {
-    println!("Compiling expression vector: {:?}", start_state);
+///
+/// The tokens matched by an accepting state are ordered by priority,
+/// and then by their order in the expression vector.
+///
+/// Tokens matching the empty string are allowed, but the scanners never
+/// produce zero-length tokens, so a warning is given for them.
+///
+/// Internally, the expressions are stored in an arena, such that each
+/// state is a vector of regex ids, which is cheap to hash and compare.
+///
+/// An error is returned when the state machine would get more than
+/// 2000 states.
+pub fn compile(expressions: ExpressionVector) -> Result<Dfa, CompileError> {
+    let mut arena = Arena::new();
+    let start_state: Vec<RegexId> = expressions
+        .expressions()
+        .iter()
+        .map(|(_, regex)| arena.insert(regex))
+        .collect();
 
     let mut transitions: Vec<(usize, Vec<(CharSet, usize)>)> = vec![];
-    let mut states: HashMap<ExpressionVector, usize> = HashMap::new();
+    let mut states: HashMap<Vec<RegexId>, usize> = HashMap::new();
     let mut accepting: HashMap<usize, Vec<String>> = HashMap::new();
     let mut error_state = None;
     states.insert(start_state.clone(), 0);
 
-    let token_types: Vec<String> = start_state.names();
+    // The names, priorities and skipped tokens are the same in every state,
+    // so they are kept here, and states only contain the expressions.
+    let token_types: Vec<String> = expressions.names();
+    let token_priorities: Vec<i32> = token_types
+        .iter()
+        .map(|name| expressions.priority(name))
+        .collect();
+    let skipped: Vec<String> = expressions.skipped().to_vec();
+    let priorities: Vec<(String, i32)> = expressions.priorities().to_vec();
 
-    // vec![v.clone()];
-    // println!("States: {:?}", states);
     let mut stack = vec![(0, start_state)];
-    while !stack.is_empty() {
-        let (state_num, state_vector) = stack.pop().unwrap();
-        // println!("State {}: {}", state_num, state_vector);
-
-        let matches = state_vector.is_nullable();
+    while let Some((state_num, state_vector)) = stack.pop() {
+        let mut matches: Vec<usize> = (0..token_types.len())
+            .filter(|i| arena.is_nullable(state_vector[*i]))
+            .collect();
         if !matches.is_empty() {
+            // Stable sort, such that the declaration order decides on equal priority.
+            matches.sort_by_key(|i| std::cmp::Reverse(token_priorities[*i]));
+            let matches = matches.iter().map(|i| token_types[*i].clone()).collect();
             accepting.insert(state_num, matches);
         }
 
-        if state_vector.is_null() {
+        if state_vector.iter().all(|id| arena.is_null(*id)) {
             error_state = Some(state_num);
         }
 
         let mut state_transitions = vec![];
 
-        for char_class in state_vector.character_classes() {
-            // println!("Char class: {}", char_class);
+        // Expressions without distinct character classes, such as the
+        // null set, and duplicate expressions do not refine the classes.
+        let mut char_classes = vec![sigma()];
+        for (i, id) in state_vector.iter().enumerate() {
+            let id_classes = arena.character_classes(*id);
+            if id_classes.len() > 1 && !state_vector[..i].contains(id) {
+                char_classes = product_intersections(char_classes, id_classes.to_vec());
+            }
+        }
+        for char_class in char_classes {
             let c = char_class.first();
-            // println!("Char: |{}|", c);
 
             // Determine new state:
-            let new_state_vector = state_vector.derivative(c);
+            let new_state_vector: Vec<RegexId> = state_vector
+                .iter()
+                .map(|id| arena.derivative(*id, c))
+                .collect();
             if !states.contains_key(&new_state_vector) {
                 let new_state_num = states.len();
-                // println!("New state number: {}", new_state_num);
-                if new_state_num > 2000 {
-                    panic!("State limit reached!");
+                if new_state_num >= MAX_STATES {
+                    return Err(CompileError {
+                        message: format!("The state machine needs more than {} states", MAX_STATES),
+                    });
                 }
                 states.insert(new_state_vector.clone(), new_state_num);
                 stack.push((new_state_num, new_state_vector.clone()));
@@ -68,12 +132,115 @@ pub fn compile(start_state: ExpressionVector) -> Dfa {
         transitions.push((state_num, state_transitions));
     }
 
-    println!("Done & done. States: {:?}", states.len());
+    // When every text can still be extended to a match, as with `.+`, no
+    // state has only null expressions. The scanners need such a state to
+    // end a token, so it is added without anything leading to it.
+    let error_state = match error_state {
+        Some(error_state) => error_state,
+        None => {
+            let error_state = transitions.len();
+            transitions.push((error_state, vec![(sigma(), error_state)]));
+            error_state
+        }
+    };
 
-    Dfa {
+    // Tokens accepted by the start state match the empty string:
+    let no_tokens = vec![];
+    let nullable = accepting.get(&0).unwrap_or(&no_tokens);
+    let warnings: Vec<String> = token_types
+        .iter()
+        .filter(|name| nullable.contains(name))
+        .map(|name| {
+            format!(
+                "Token {} matches the empty string, which is never produced",
+                name
+            )
+        })
+        .collect();
+
+    Ok(Dfa {
         token_types,
         transitions,
         accepting,
-        error_state: error_state.unwrap(),
+        error_state,
+        skipped,
+        priorities,
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::compile;
+    use crate::expression::sigma;
+    use crate::{minimize, scan, ExpressionVector, Regex};
+
+    fn keywords() -> ExpressionVector {
+        ExpressionVector::new(vec![
+            ("ID".to_owned(), Regex::parse_or_panic("[a-z]+")),
+            ("IF".to_owned(), Regex::parse_or_panic("if")),
+            ("NUMBER".to_owned(), Regex::parse_or_panic("[0-9]+")),
+        ])
+    }
+
+    #[test]
+    fn priority_orders_accepting() {
+        let dfa = compile(keywords().with_priorities(vec![("IF".to_owned(), 1)])).unwrap();
+        let matches: Vec<&Vec<String>> = dfa.accepting.values().filter(|m| m.len() > 1).collect();
+        assert_eq!(matches, vec![&vec!["IF".to_owned(), "ID".to_owned()]]);
+        assert!(dfa.warnings.is_empty());
+    }
+
+    #[test]
+    fn nullable_warns() {
+        let ev = ExpressionVector::new(vec![
+            ("NUMBER".to_owned(), Regex::parse_or_panic("[0-9]+")),
+            ("SPACE".to_owned(), Regex::parse_or_panic("[ ]*")),
+        ]);
+        let dfa = compile(ev).unwrap();
+        assert_eq!(
+            dfa.warnings,
+            vec!["Token SPACE matches the empty string, which is never produced"]
+        );
+    }
+
+    #[test]
+    fn comment_terminates() {
+        // The derivatives of a complement only end up in a few distinct
+        // states when alternations are kept in normal form.
+        let ev = ExpressionVector::new(vec![(
+            "COMMENT".to_owned(),
+            Regex::parse_or_panic("/\\*!(.*\\*/.*)\\*/"),
+        )]);
+        let dfa = compile(ev).unwrap();
+        assert_eq!(dfa.transitions.len(), 6);
+    }
+
+    #[test]
+    fn without_dead_state() {
+        for pattern in &[".+", "!(abc)&.+"] {
+            let ev = ExpressionVector::new(vec![("X".to_owned(), Regex::parse_or_panic(pattern))]);
+            let dfa = compile(ev).unwrap();
+            let error_state = dfa.transitions.iter().find(|(s, _)| *s == dfa.error_state);
+            assert_eq!(
+                error_state,
+                Some(&(dfa.error_state, vec![(sigma(), dfa.error_state)]))
+            );
+            assert!(!dfa.accepting.contains_key(&dfa.error_state));
+            assert_eq!(scan(minimize(dfa), "ab").unwrap().len(), 1);
+        }
+    }
+
+    #[test]
+    fn state_limit() {
+        // Remembering the last 12 characters takes 4096 states.
+        let ev = ExpressionVector::new(vec![(
+            "X".to_owned(),
+            Regex::parse_or_panic("[ab]*a[ab]{11}"),
+        )]);
+        match compile(ev) {
+            Ok(_) => panic!("Expected the state limit to be reached"),
+            Err(err) => assert_eq!(err.message, "The state machine needs more than 2000 states"),
+        }
     }
 }
@@ -9,7 +9,7 @@ pub fn write_dot(dfa: Dfa) -> std::io::Result<()> {
     writeln!(f, "digraph state_machine {{")?;
     for (from_state, transitions) in all_transitions {
         for (char_set, to_state) in transitions {
-            let label = format!("{}", char_set);
+            let label = format!("{}", char_set).escape_debug().to_string();
             writeln!(f, "  {} -> {} [label=\"{}\"];", from_state, to_state, label)?;
         }
     }
@@ -9,15 +9,6 @@ pub fn write_c_code(dfa: &Dfa, basename: &str) -> std::io::Result<()> {
     Ok(())
 }
 
-// Format string properly, using escape codes such as \n and friends.
-fn my_escaper(
-    val: &tera::Value,
-    _ctx: &std::collections::HashMap<String, tera::Value>,
-) -> Result<tera::Value, tera::Error> {
-    let escaped: String = val.as_str().unwrap().escape_default().to_string();
-    Ok(tera::Value::String(escaped))
-}
-
 fn create_tera_context(dfa: &Dfa, basename: &str) -> tera::Context {
     let (token_types, all_transitions, accepting_states, error_state) = (
         &dfa.token_types,
@@ -34,8 +25,8 @@ fn create_tera_context(dfa: &Dfa, basename: &str) -> tera::Context {
             for (r, target_state) in t {
                 for y in &r.ranges {
                     t2.push(StateTransition {
-                        begin: y.begin,
-                        end: y.end,
+                        begin: code_point(y.begin),
+                        end: code_point(y.end),
                         next_state: *target_state,
                     });
                 }
@@ -51,6 +42,7 @@ fn create_tera_context(dfa: &Dfa, basename: &str) -> tera::Context {
     context.insert("basename", basename);
     context.insert("error_state", error_state);
     context.insert("token_types", token_types);
+    context.insert("skipped", &dfa.skipped);
     context.insert("accepting_states", accepting_states);
     context.insert("all_transitions", &transitions2);
     context
@@ -64,7 +56,6 @@ fn write_c_header(dfa: &Dfa, basename: &str) -> std::io::Result<()> {
     let template_text = std::include_str!("templates/c/header.txt");
     let context = create_tera_context(dfa, basename);
     let mut t = Tera::default();
-    t.register_filter("my_escaper", my_escaper);
     let generated_src = t.render_str(template_text, &context).unwrap();
 
     write!(f, "{}", generated_src)?;
@@ -72,10 +63,15 @@ fn write_c_header(dfa: &Dfa, basename: &str) -> std::io::Result<()> {
     Ok(())
 }
 
+/// Format a character as C integer constant.
+fn code_point(c: char) -> String {
+    format!("0x{:X}", c as u32)
+}
+
 #[derive(serde::Serialize)]
 struct StateTransition {
-    begin: char,
-    end: char,
+    begin: String,
+    end: String,
     next_state: usize,
 }
 
@@ -89,7 +85,6 @@ fn write_c_source(dfa: &Dfa, basename: &str) -> std::io::Result<()> {
     let template_text = std::include_str!("templates/c/source.txt");
     let context = create_tera_context(dfa, basename);
     let mut t = Tera::default();
-    t.register_filter("my_escaper", my_escaper);
 
     let generated_src = t.render_str(template_text, &context).unwrap();
 
@@ -101,3 +96,74 @@ fn write_c_source(dfa: &Dfa, basename: &str) -> std::io::Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::write_c_code;
+    use crate::{compile, ExpressionVector, Regex};
+    use std::process::Command;
+
+    /// Compile the generated scanner with the system C compiler, and count
+    /// the single character tokens in some byte sequences, followed by E
+    /// when the scanner ends with an error.
+    #[test]
+    fn c_decodes_utf8() {
+        let dir = std::env::temp_dir().join(format!("zowski-c-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let basename = dir.join("scanner");
+        let ev = ExpressionVector::new(vec![("ANY".to_owned(), Regex::parse_or_panic("."))]);
+        write_c_code(&compile(ev).unwrap(), basename.to_str().unwrap()).unwrap();
+
+        let driver = r#"
+#include <stdio.h>
+#include <string.h>
+#include "scanner.h"
+
+static void count(char* text)
+{
+    zowski_lexer_t* lex = zowski_lexer_new();
+    zowski_lexer_feed(lex, text, strlen(text));
+    int n = 0;
+    zowski_result_t res;
+    while ((res = zowski_lexer_next_token(lex)) == ZOWSKI_RESULT_OK) n++;
+    printf("%d%s ", n, (res == ZOWSKI_RESULT_ERROR) ? "E" : "");
+    zowski_lexer_delete(lex);
+}
+
+int main()
+{
+    count("\xC3\xA9");              // U+00E9
+    count("\xF0\x9F\x98\x80");      // U+1F600
+    count("\xC3");                  // Truncated
+    count("\xC0\x80");              // Overlong U+0000
+    count("\xE0\x80\x80");          // Overlong U+0000
+    count("\xF0\x8F\xBF\xBF");      // Overlong U+FFFF
+    count("\xED\xA0\x80");          // Surrogate U+D800
+    count("\xF4\x90\x80\x80");      // U+110000
+    count("\xE9\xFF");              // Latin-1 text
+    count("a\x80" "b");             // Stray continuation byte
+    return 0;
+}
+"#;
+        std::fs::write(dir.join("driver.c"), driver).unwrap();
+        let compiled = Command::new("cc")
+            .current_dir(&dir)
+            .args(["-o", "driver", "driver.c", "scanner.c"])
+            .status();
+        match compiled {
+            Ok(status) => assert!(status.success()),
+            Err(_) => {
+                eprintln!("No C compiler, skipping");
+                return;
+            }
+        }
+        let output = Command::new(dir.join("driver")).output().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // Invalid sequences are not taken as characters.
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "1 1 0E 0E 0E 0E 0E 0E 0E 1E "
+        );
+    }
+}
@@ -1,9 +1,19 @@
+use crate::normal::{self, Kind, Node, Terms};
+use crate::parse::{parse_regex, ParseError};
 use crate::range::Range;
 use crate::rangeset::RangeSet;
+use crate::unicode::case_fold_closure;
 
 pub type CharSet = RangeSet<char>;
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+/// A regular expression.
+///
+/// The smart constructors, such as the `|`, `+` and `&` operators, keep
+/// a regex in a normal form. Alternations and intersections are nested to
+/// the right, with their operands sorted and without duplicates, and
+/// concatenations are nested to the right. This way equivalent derivatives
+/// are often equal, which bounds the number of states of the scanner.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Regex {
     /// The empty string
     Epsilon,
@@ -47,13 +57,23 @@ pub enum Regex {
 }
 
 impl Regex {
+    /// Parse a regex, and panic when it is invalid.
+    ///
+    /// This is a convenience for patterns which are known to be
+    /// valid, such as literals in source code. Use `str::parse`
+    /// or `Regex::try_from` for patterns from other sources.
+    pub fn parse_or_panic(re: &str) -> Self {
+        match parse_regex(re) {
+            Ok(regex) => regex,
+            Err(err) => panic!("Invalid regex {:?}: {}", re, err),
+        }
+    }
+
     /// Apply the Kleene closure operator to this regex.
+    ///
+    /// Some trivial cases are simplified, such that `a**` equals `a*`.
     pub fn kleene(self) -> Self {
-        if self.is_epsilon() {
-            self
-        } else {
-            Regex::Kleene(Box::new(self))
-        }
+        normal::kleene(&mut Trees, self)
     }
 
     /// Apply the + operator to this regex
@@ -61,15 +81,66 @@ impl Regex {
         self.clone() + self.kleene()
     }
 
+    /// Apply the ? operator to this regex, making it optional.
+    pub fn optional(self) -> Self {
+        if self.is_nullable() {
+            self
+        } else {
+            self | Regex::epsilon()
+        }
+    }
+
+    /// Repeat this regex at least `min` and at most `max` times.
+    ///
+    /// When `max` is `None`, there is no upper bound.
+    pub fn repeat(self, min: usize, max: Option<usize>) -> Self {
+        let tail = match max {
+            Some(max) => {
+                assert!(min <= max);
+                // Nest the optional repetitions, like: (r(r)?)?
+                (min..max).fold(Regex::epsilon(), |tail, _| {
+                    (self.clone() + tail) | Regex::epsilon()
+                })
+            }
+            None => self.clone().kleene(),
+        };
+        (0..min).fold(tail, |tail, _| self.clone() + tail)
+    }
+
     /// Invert the regex.
+    ///
+    /// The result matches any string which is not matched
+    /// by this regex. The complement of the null set is `.*`,
+    /// and the other way around.
     pub fn logical_not(self) -> Self {
+        normal::logical_not(&mut Trees, self)
+    }
+
+    /// Make this regex case insensitive.
+    ///
+    /// Every set of symbols is extended with the characters which are
+    /// equal under unicode simple case folding.
+    pub fn case_insensitive(self) -> Self {
         match self {
-            Regex::LogicalNot(r) => *r,
-            Regex::SymbolSet(s) => Regex::SymbolSet(sigma().difference(&s)),
-            other => Regex::LogicalNot(Box::new(other)),
+            Regex::Epsilon => self,
+            Regex::SymbolSet(s) => Regex::SymbolSet(case_fold_closure(&s)),
+            Regex::Kleene(r) => r.case_insensitive().kleene(),
+            Regex::Alternation { left, right } => {
+                left.case_insensitive() | right.case_insensitive()
+            }
+            Regex::Concatenation { left, right } => {
+                left.case_insensitive() + right.case_insensitive()
+            }
+            Regex::LogicalAnd { left, right } => left.case_insensitive() & right.case_insensitive(),
+            Regex::LogicalNot(r) => r.case_insensitive().logical_not(),
         }
     }
 
+    /// Match strings matched by this regex, but not by the other regex.
+    pub fn difference(self, other: Self) -> Self {
+        self & other.logical_not()
+    }
+
     /// Create the empty string
     pub fn epsilon() -> Self {
         Regex::Epsilon
@@ -80,6 +151,12 @@ impl Regex {
         Regex::SymbolSet(CharSet::new(c))
     }
 
+    /// Create an expression matching exactly the given text.
+    pub fn literal(text: &str) -> Self {
+        text.chars()
+            .fold(Regex::epsilon(), |r, c| r + Regex::symbol(c))
+    }
+
     /// Create expression which matches a range of characters.
     pub fn symbol_range(begin: char, end: char) -> Self {
         Regex::SymbolSet(CharSet::new2(begin, end))
@@ -113,6 +190,14 @@ impl Regex {
         }
     }
 
+    /// Test if this regex matches any string, like `.*`.
+    pub fn is_universal(&self) -> bool {
+        match self {
+            Regex::Kleene(r) => matches!(r.as_ref(), Regex::SymbolSet(s) if *s == sigma()),
+            _ => false,
+        }
+    }
+
     /// Determine if this regex is nullable.
     ///
     /// This means that the regex can be reduced
@@ -129,6 +214,13 @@ impl Regex {
         }
     }
 
+    /// Test if the given text is matched by this regex.
+    pub fn matches(&self, text: &str) -> bool {
+        text.chars()
+            .fold(self.clone(), |r, c| r.derivative(c))
+            .is_nullable()
+    }
+
     /// Construct the derivative of this regex with respect to
     /// some other character.
     pub fn derivative(&self, c: char) -> Regex {
@@ -187,11 +279,11 @@ impl Regex {
 }
 
 /// Return whole alphabeth
-fn sigma() -> CharSet {
-    // TODO: expand to more code points!
-    // space = ' ' = 32
-    // tilde = '~' = 126
-    CharSet::new2(' ', '~') | CharSet::new('\n')
+///
+/// This is the full unicode range, all the way from
+/// U+0000 to U+10FFFF.
+pub fn sigma() -> CharSet {
+    CharSet::new2('\0', std::char::MAX)
 }
 
 /// Yeah...
@@ -209,7 +301,8 @@ pub fn product_intersections(class1: Vec<CharSet>, class2: Vec<CharSet>) -> Vec<
         }
     }
 
-    assert_eq!(unify(intersections.clone()), sigma());
+    // The character classes together must cover the whole alphabeth:
+    debug_assert_eq!(unify(intersections.clone()), sigma());
     // println!("Intersecion of {:?} and {:?}: {:?}", class1, class2, intersections);
     intersections
 }
@@ -224,7 +317,7 @@ impl std::ops::BitOr for Regex {
     type Output = Self;
 
     fn bitor(self, rhs: Self) -> Self {
-        alternation(self, rhs)
+        normal::alternation(&mut Trees, self, rhs)
     }
 }
 
@@ -232,7 +325,7 @@ impl std::ops::Add for Regex {
     type Output = Self;
 
     fn add(self, rhs: Self) -> Self {
-        concatenate(self, rhs)
+        normal::concatenate(&mut Trees, self, rhs)
     }
 }
 
@@ -240,63 +333,104 @@ impl std::ops::BitAnd for Regex {
     type Output = Self;
 
     fn bitand(self, rhs: Self) -> Self {
-        logical_and(self, rhs)
+        normal::logical_and(&mut Trees, self, rhs)
     }
 }
 
-impl From<&str> for Regex {
-    fn from(re: &str) -> Self {
-        use crate::parse::parse_regex;
-        parse_regex(re).unwrap()
+impl std::ops::Sub for Regex {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        self.difference(rhs)
     }
 }
 
-/// Alternation / logical or operation
-fn alternation(left: Regex, right: Regex) -> Regex {
-    if left.is_null() {
-        right
-    } else if right.is_null() {
-        left
-    } else if let (Regex::SymbolSet(a), Regex::SymbolSet(b)) = (&left, &right) {
-        Regex::SymbolSet(a.union(b))
-    } else {
-        Regex::Alternation {
-            left: Box::new(left),
-            right: Box::new(right),
-        }
+impl std::str::FromStr for Regex {
+    type Err = ParseError;
+
+    fn from_str(re: &str) -> Result<Self, Self::Err> {
+        parse_regex(re)
     }
 }
 
-fn logical_and(left: Regex, right: Regex) -> Regex {
-    if left.is_null() {
-        left
-    } else if right.is_null() {
-        right
-    } else {
-        Regex::LogicalAnd {
-            left: Box::new(left),
-            right: Box::new(right),
-        }
+impl std::convert::TryFrom<&str> for Regex {
+    type Error = ParseError;
+
+    fn try_from(re: &str) -> Result<Self, Self::Error> {
+        parse_regex(re)
     }
 }
 
-fn concatenate(left: Regex, right: Regex) -> Regex {
-    if left.is_null() {
-        left
-    } else if right.is_null() {
-        right
-    } else if left.is_epsilon() {
-        right
-    } else if right.is_epsilon() {
-        left
-    } else {
-        Regex::Concatenation {
-            left: Box::new(left),
-            right: Box::new(right),
+/// Regexes as trees, as built by the constructors of `Regex`.
+struct Trees;
+
+impl Terms for Trees {
+    type Term = Regex;
+
+    fn make(&mut self, node: Node<Regex>) -> Regex {
+        match node {
+            Node::Epsilon => Regex::Epsilon,
+            Node::SymbolSet(s) => Regex::SymbolSet(s),
+            Node::Kleene(r) => Regex::Kleene(Box::new(r)),
+            Node::Alternation(left, right) => Regex::Alternation {
+                left: Box::new(left),
+                right: Box::new(right),
+            },
+            Node::Concatenation(left, right) => Regex::Concatenation {
+                left: Box::new(left),
+                right: Box::new(right),
+            },
+            Node::LogicalAnd(left, right) => Regex::LogicalAnd {
+                left: Box::new(left),
+                right: Box::new(right),
+            },
+            Node::LogicalNot(r) => Regex::LogicalNot(Box::new(r)),
+        }
+    }
+
+    fn take(&self, term: Regex) -> Node<Regex> {
+        match term {
+            Regex::Epsilon => Node::Epsilon,
+            Regex::SymbolSet(s) => Node::SymbolSet(s),
+            Regex::Kleene(r) => Node::Kleene(*r),
+            Regex::Alternation { left, right } => Node::Alternation(*left, *right),
+            Regex::Concatenation { left, right } => Node::Concatenation(*left, *right),
+            Regex::LogicalAnd { left, right } => Node::LogicalAnd(*left, *right),
+            Regex::LogicalNot(r) => Node::LogicalNot(*r),
+        }
+    }
+
+    fn kind(&self, term: &Regex) -> Kind {
+        match term {
+            Regex::Epsilon => Kind::Epsilon,
+            Regex::SymbolSet(_) => Kind::SymbolSet,
+            Regex::Kleene(_) => Kind::Kleene,
+            Regex::Alternation { .. } => Kind::Alternation,
+            Regex::Concatenation { .. } => Kind::Concatenation,
+            Regex::LogicalAnd { .. } => Kind::LogicalAnd,
+            Regex::LogicalNot(_) => Kind::LogicalNot,
         }
     }
+
+    fn symbols<'t>(&'t self, term: &'t Regex) -> Option<&'t CharSet> {
+        match term {
+            Regex::SymbolSet(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn is_nullable(&self, term: &Regex) -> bool {
+        term.is_nullable()
+    }
+
+    fn is_universal(&self, term: &Regex) -> bool {
+        term.is_universal()
+    }
 }
 
+/// Display the structure of the regex, for debugging.
+///
+/// Use `print_regex` to get a pattern which can be parsed again.
 impl std::fmt::Display for Regex {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
@@ -314,6 +448,7 @@ impl std::fmt::Display for Regex {
 #[cfg(test)]
 mod tests {
     use super::Regex;
+    use std::convert::TryFrom;
 
     #[test]
     fn blabla1() {
@@ -330,4 +465,101 @@ mod tests {
         assert!(expr.derivative('A') == Regex::symbol('B'));
         assert!(expr.derivative('B').is_null());
     }
+
+    #[test]
+    fn normal_form() {
+        let (a, b, c) = (
+            Regex::parse_or_panic("a*"),
+            Regex::parse_or_panic("bc"),
+            Regex::parse_or_panic("!c"),
+        );
+        // Associative, commutative and idempotent:
+        assert_eq!(
+            (a.clone() | b.clone()) | c.clone(),
+            c.clone() | (b.clone() | a.clone())
+        );
+        assert_eq!(a.clone() | b.clone() | a.clone(), b.clone() | a.clone());
+        assert_eq!(
+            (a.clone() & b.clone()) & c.clone(),
+            c.clone() & (a.clone() & b.clone())
+        );
+        assert_eq!(a.clone() & a.clone(), a);
+        assert_eq!(
+            (a.clone() + b.clone()) + c.clone(),
+            a.clone() + (b.clone() + c.clone())
+        );
+
+        // Similarity rules:
+        assert_eq!(a.clone().kleene(), a);
+        assert_eq!(c.clone().logical_not().logical_not(), c);
+        assert_eq!(Regex::null() & a.clone(), Regex::null());
+        assert_eq!(Regex::null().logical_not() & b.clone(), b);
+        assert_eq!(Regex::sigma().kleene() | b.clone(), Regex::sigma().kleene());
+        assert_eq!(Regex::epsilon() + b.clone(), b);
+        assert_eq!(Regex::epsilon() | a.clone(), a);
+        assert_eq!(Regex::epsilon() & a.clone(), Regex::epsilon());
+        assert_eq!(Regex::epsilon() & b, Regex::null());
+        assert_eq!(
+            Regex::symbol('x') | c.clone() | Regex::symbol('y'),
+            Regex::parse_or_panic("[xy]") | c
+        );
+    }
+
+    #[test]
+    fn literal() {
+        let expr = Regex::literal("AB");
+        assert_eq!(expr, Regex::symbol('A') + Regex::symbol('B'));
+        assert!(expr.matches("AB"));
+        assert!(!expr.matches("A"));
+        assert!(Regex::literal("").is_epsilon());
+    }
+
+    #[test]
+    fn case_insensitive() {
+        let expr = Regex::literal("begin").case_insensitive();
+        assert!(expr.matches("begin"));
+        assert!(expr.matches("BeGiN"));
+        assert!(!expr.matches("BEGINS"));
+
+        let expr = (Regex::literal("ab") - Regex::literal("aB")).case_insensitive();
+        assert!(!expr.matches("ab"));
+        assert!(!expr.matches("AB"));
+    }
+
+    #[test]
+    fn from_str() {
+        let expr: Regex = "A|B".parse().unwrap();
+        assert_eq!(expr, Regex::symbol('A') | Regex::symbol('B'));
+        assert_eq!(Regex::try_from("A|B").unwrap(), expr);
+        assert_eq!(Regex::parse_or_panic("A|B"), expr);
+
+        let err = "A|".parse::<Regex>().unwrap_err();
+        assert_eq!(err.span, 2..3);
+        assert!(Regex::try_from("[A").is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn parse_or_panic_on_error() {
+        Regex::parse_or_panic("(A");
+    }
+
+    #[test]
+    fn complement_of_symbol() {
+        let expr = Regex::symbol('A').logical_not();
+        assert!(expr.is_nullable());
+        assert!(!expr.matches("A"));
+        assert!(expr.matches("B"));
+        assert!(expr.matches("AA"));
+    }
+
+    #[test]
+    fn unicode_alphabet() {
+        let expr = Regex::sigma().kleene();
+        assert!(expr.matches("\t\r\0"));
+        assert!(expr.matches("λ字\u{10FFFF}"));
+        let classes = Regex::symbol('λ').character_classes();
+        assert_eq!(classes.len(), 2);
+        assert!(classes[1].contains('\u{10FFFF}'));
+    }
 }
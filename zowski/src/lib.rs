@@ -1,19 +1,33 @@
+mod analysis;
+mod arena;
 mod dfa;
 mod dot;
 mod export_to_c;
 mod expression;
+mod minimize;
+mod normal;
 mod parse;
+mod print;
+#[cfg(test)]
+mod random;
 mod range;
 mod rangeset;
 mod scanner;
 mod spec;
+mod unicode;
+#[rustfmt::skip]
+mod unicode_tables;
 mod vector;
 // pub mod lexer;
 
-pub use dfa::compile;
+pub use analysis::{overlapping_tokens, shadowed_tokens, Overlap, Shadowed};
+pub use dfa::{compile, CompileError};
 pub use dot::write_dot;
 pub use export_to_c::write_c_code;
 pub use expression::Regex;
+pub use minimize::minimize;
+pub use parse::ParseError;
+pub use print::print_regex;
 pub use scanner::scan;
-pub use spec::{read_spec, TokenSpec};
+pub use spec::{parse_spec, read_spec, SpecError, SpecParseError, TokenSpec};
 pub use vector::ExpressionVector;
@@ -0,0 +1,291 @@
+//! Minimization of state machines.
+//!
+//! This uses Hopcroft's partition refinement. States are only merged
+//! when they accept the same tokens, in the same order, so the minimized
+//! state machine produces the same tokens as the original one.
+
+use crate::dfa::Dfa;
+use crate::expression::CharSet;
+use crate::range::{ItemsBetween, Range};
+use std::collections::HashMap;
+
+/// Merge equivalent states of the state machine.
+///
+/// The start state remains state 0.
+pub fn minimize(dfa: Dfa) -> Dfa {
+    let states: Vec<usize> = dfa.transitions.iter().map(|(s, _)| *s).collect();
+    let index: HashMap<usize, usize> = states.iter().enumerate().map(|(i, s)| (*s, i)).collect();
+    let symbols = elementary_ranges(&dfa);
+
+    // Target state, for each state and symbol. Both the symbols and the
+    // ranges of a state are sorted, so they are walked side by side.
+    let delta: Vec<Vec<usize>> = dfa
+        .transitions
+        .iter()
+        .map(|(_, transitions)| {
+            let mut ranges: Vec<(char, usize)> = transitions
+                .iter()
+                .flat_map(|(set, target)| set.ranges.iter().map(move |r| (r.begin, *target)))
+                .collect();
+            ranges.sort_unstable();
+            let mut current = 0;
+            symbols
+                .iter()
+                .map(|symbol| {
+                    while current + 1 < ranges.len() && ranges[current + 1].0 <= symbol.begin {
+                        current += 1;
+                    }
+                    index[&ranges[current].1]
+                })
+                .collect()
+        })
+        .collect();
+
+    let blocks = refine(&dfa, &states, &delta, symbols.len());
+
+    // Number the blocks by their lowest original state, such that the
+    // start state stays 0.
+    let mut block_of = vec![0; states.len()];
+    let mut representatives: Vec<usize> = vec![];
+    let mut ordered: Vec<Vec<usize>> = blocks;
+    for block in &mut ordered {
+        block.sort_by_key(|s| states[*s]);
+    }
+    ordered.sort_by_key(|block| states[block[0]]);
+    for (number, block) in ordered.iter().enumerate() {
+        for s in block {
+            block_of[*s] = number;
+        }
+        representatives.push(block[0]);
+    }
+
+    let mut transitions = vec![];
+    let mut accepting = HashMap::new();
+    for (number, representative) in representatives.iter().enumerate() {
+        // Glue the ranges going to the same target together:
+        let mut targets: Vec<(usize, Vec<Range<char>>)> = vec![];
+        for (symbol, target) in symbols.iter().zip(&delta[*representative]) {
+            let target = block_of[*target];
+            match targets.iter_mut().find(|(t, _)| *t == target) {
+                Some((_, ranges)) => ranges.push(symbol.clone()),
+                None => targets.push((target, vec![symbol.clone()])),
+            }
+        }
+        let state_transitions = targets
+            .into_iter()
+            .map(|(target, ranges)| (CharSet::from_ranges(ranges), target))
+            .collect();
+        transitions.push((number, state_transitions));
+
+        if let Some(tokens) = dfa.accepting.get(&states[*representative]) {
+            accepting.insert(number, tokens.clone());
+        }
+    }
+
+    Dfa {
+        token_types: dfa.token_types,
+        transitions,
+        accepting,
+        error_state: block_of[index[&dfa.error_state]],
+        skipped: dfa.skipped,
+        priorities: dfa.priorities,
+        warnings: dfa.warnings,
+    }
+}
+
+/// Split the alphabet into the largest ranges on which no transition
+/// makes a difference.
+fn elementary_ranges(dfa: &Dfa) -> Vec<Range<char>> {
+    let mut begins: Vec<char> = vec![];
+    for (_, transitions) in &dfa.transitions {
+        for (set, _) in transitions {
+            for range in &set.ranges {
+                begins.push(range.begin);
+                if range.end < std::char::MAX {
+                    begins.push(range.end.add_index(1));
+                }
+            }
+        }
+    }
+    begins.push('\0');
+    begins.sort_unstable();
+    begins.dedup();
+
+    let mut ranges = vec![];
+    for (i, begin) in begins.iter().enumerate() {
+        let end = match begins.get(i + 1) {
+            Some(next) => next.sub_index(1),
+            None => std::char::MAX,
+        };
+        ranges.push(Range::new(*begin, end));
+    }
+    ranges
+}
+
+/// Hopcroft's partition refinement.
+///
+/// Start with the states grouped by the tokens they accept, and split
+/// groups until all states in a group agree on the group of their
+/// successor, for each symbol.
+fn refine(
+    dfa: &Dfa,
+    states: &[usize],
+    delta: &[Vec<usize>],
+    symbol_count: usize,
+) -> Vec<Vec<usize>> {
+    // Predecessors, for each symbol and state:
+    let mut inverse: Vec<Vec<Vec<usize>>> = vec![vec![vec![]; states.len()]; symbol_count];
+    for (source, targets) in delta.iter().enumerate() {
+        for (symbol, target) in targets.iter().enumerate() {
+            inverse[symbol][*target].push(source);
+        }
+    }
+
+    let mut initial: Vec<(Option<&Vec<String>>, Vec<usize>)> = vec![];
+    for (i, state) in states.iter().enumerate() {
+        let tokens = dfa.accepting.get(state);
+        match initial.iter_mut().find(|(t, _)| *t == tokens) {
+            Some((_, block)) => block.push(i),
+            None => initial.push((tokens, vec![i])),
+        }
+    }
+    let mut blocks: Vec<Vec<usize>> = initial.into_iter().map(|(_, block)| block).collect();
+    let mut block_of = vec![0; states.len()];
+    for (number, block) in blocks.iter().enumerate() {
+        for s in block {
+            block_of[*s] = number;
+        }
+    }
+
+    let mut worklist: Vec<usize> = (0..blocks.len()).collect();
+    let mut in_worklist = vec![true; blocks.len()];
+    while let Some(splitter) = worklist.pop() {
+        in_worklist[splitter] = false;
+        let splitter_states = blocks[splitter].clone();
+        for predecessors in &inverse {
+            // Group the predecessors of the splitter by their block:
+            let mut touched: HashMap<usize, Vec<usize>> = HashMap::new();
+            for target in &splitter_states {
+                for source in &predecessors[*target] {
+                    touched.entry(block_of[*source]).or_default().push(*source);
+                }
+            }
+
+            let mut touched: Vec<(usize, Vec<usize>)> = touched.into_iter().collect();
+            touched.sort_unstable_by_key(|(block, _)| *block);
+            for (block, inside) in touched {
+                if inside.len() == blocks[block].len() {
+                    continue;
+                }
+
+                // Split the block, into the part leading into the splitter,
+                // and the rest.
+                let new_block = blocks.len();
+                for s in &inside {
+                    block_of[*s] = new_block;
+                }
+                let outside: Vec<usize> = blocks[block]
+                    .iter()
+                    .filter(|s| block_of[**s] == block)
+                    .cloned()
+                    .collect();
+                blocks[block] = outside;
+                blocks.push(inside);
+
+                if in_worklist[block] || blocks[new_block].len() < blocks[block].len() {
+                    worklist.push(new_block);
+                    in_worklist.push(true);
+                } else {
+                    worklist.push(block);
+                    in_worklist[block] = true;
+                    in_worklist.push(false);
+                }
+            }
+        }
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::minimize;
+    use crate::random::Random;
+    use crate::{compile, parse_spec, scan, ExpressionVector, Regex};
+
+    fn tokens(dfa: crate::dfa::Dfa, text: &str) -> Result<Vec<(String, String)>, String> {
+        let tokens = scan(dfa, text)?;
+        Ok(tokens
+            .iter()
+            .map(|token| {
+                let (typ, text) = token.parts();
+                (typ.to_owned(), text.to_owned())
+            })
+            .collect())
+    }
+
+    #[test]
+    fn merges_equivalent_states() {
+        // After `a` and after `b`, the same texts are accepted.
+        let ev = ExpressionVector::new(vec![(
+            "X".to_owned(),
+            Regex::parse_or_panic("a(cd|ce)|b(c[de])"),
+        )]);
+        let dfa = compile(ev).unwrap();
+        let minimized = minimize(dfa.clone());
+        assert!(minimized.transitions.len() < dfa.transitions.len());
+        assert_eq!(minimized.transitions.len(), 5);
+        assert_eq!(minimized.transitions[0].0, 0);
+    }
+
+    #[test]
+    fn keeps_token_identity() {
+        let ev = ExpressionVector::new(vec![
+            ("A".to_owned(), Regex::parse_or_panic("x[0-9]")),
+            ("B".to_owned(), Regex::parse_or_panic("y[0-9]")),
+        ]);
+        let minimized = minimize(compile(ev).unwrap());
+        let expected = vec![
+            ("A".to_owned(), "x1".to_owned()),
+            ("B".to_owned(), "y2".to_owned()),
+        ];
+        assert_eq!(tokens(minimized, "x1y2"), Ok(expected));
+    }
+
+    const SPEC: &str = r#"
+let digit = [0-9]
+
+IF [priority=1]: if
+DO [priority=1]: do
+IDENTIFIER: [a-z]([a-z]|{digit})*
+UNSIGNED: {digit}+
+REAL: {digit}+\.{digit}*
+ASSIGN: :=
+COLON: :
+DOT: "."
+# After < and after >, the same texts are accepted.
+ANGLES: <(<>|<=)|>(<[=>])
+WHITESPACE [skip]: [ \n]+
+COMMENT [skip]: "{"[^}]*"}"
+"#;
+
+    #[test]
+    fn tokenizes_identically() {
+        let ev = ExpressionVector::from_specs(parse_spec(SPEC, "test.zowski").unwrap());
+        let dfa = compile(ev).unwrap();
+        let minimized = minimize(dfa.clone());
+        assert!(minimized.transitions.len() < dfa.transitions.len());
+
+        let alphabet: Vec<char> = "ifdox09.:=<> \n{}_".chars().collect();
+        let mut random = Random(0x9E37_79B9_7F4A_7C15);
+        for _ in 0..300 {
+            let length = random.next(30);
+            let text: String = (0..length).map(|_| random.choose(&alphabet)).collect();
+            assert_eq!(
+                tokens(dfa.clone(), &text),
+                tokens(minimized.clone(), &text),
+                "Text {:?}",
+                text
+            );
+        }
+    }
+}
@@ -0,0 +1,242 @@
+//! Normal form of regexes.
+//!
+//! Regexes are kept in a normal form, such that the derivatives of a regex
+//! only give a finite number of distinct regexes:
+//!
+//! - Alternations and intersections are associative, commutative and
+//!   idempotent: their operands are flattened, sorted, deduplicated and
+//!   nested to the right, and their symbol sets are merged.
+//! - Concatenations are nested to the right.
+//! - Trivial cases are simplified, such that `a**` is `a*` and `!!a` is `a`.
+//!
+//! The rules are written once, for any storage of regexes. These are the
+//! trees of `Regex`, and the arena which is used while compiling.
+
+use crate::expression::{sigma, CharSet};
+
+/// A regex node, with operands of type `T`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Node<T> {
+    Epsilon,
+    SymbolSet(CharSet),
+    Kleene(T),
+    Alternation(T, T),
+    Concatenation(T, T),
+    LogicalAnd(T, T),
+    LogicalNot(T),
+}
+
+/// The kind of a regex node, without its operands.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Kind {
+    Epsilon,
+    SymbolSet,
+    Kleene,
+    Alternation,
+    Concatenation,
+    LogicalAnd,
+    LogicalNot,
+}
+
+/// Storage of regexes, in which regexes are built and taken apart.
+pub trait Terms {
+    /// A stored regex. Operands are sorted in this order.
+    type Term: Ord;
+
+    /// Build a node as it is, without simplifying it.
+    fn make(&mut self, node: Node<Self::Term>) -> Self::Term;
+
+    /// Take a regex apart, into its node.
+    fn take(&self, term: Self::Term) -> Node<Self::Term>;
+
+    fn kind(&self, term: &Self::Term) -> Kind;
+
+    /// Retrieve the set of a symbol set.
+    fn symbols<'t>(&'t self, term: &'t Self::Term) -> Option<&'t CharSet>;
+
+    fn is_nullable(&self, term: &Self::Term) -> bool;
+
+    /// Test if the regex is `.*`, which matches any string.
+    fn is_universal(&self, term: &Self::Term) -> bool;
+}
+
+fn is_epsilon<T: Terms>(terms: &T, term: &T::Term) -> bool {
+    terms.kind(term) == Kind::Epsilon
+}
+
+fn is_null<T: Terms>(terms: &T, term: &T::Term) -> bool {
+    matches!(terms.symbols(term), Some(symbols) if symbols.is_empty())
+}
+
+fn null<T: Terms>(terms: &mut T) -> T::Term {
+    terms.make(Node::SymbolSet(CharSet::empty()))
+}
+
+fn universal<T: Terms>(terms: &mut T) -> T::Term {
+    let sigma = terms.make(Node::SymbolSet(sigma()));
+    terms.make(Node::Kleene(sigma))
+}
+
+/// Kleene closure, where `a**` is `a*`, and `(a?)*` is `a*`.
+pub fn kleene<T: Terms>(terms: &mut T, term: T::Term) -> T::Term {
+    match terms.kind(&term) {
+        Kind::Epsilon | Kind::Kleene => term,
+        Kind::Alternation => match terms.take(term) {
+            Node::Alternation(left, right) if is_epsilon(terms, &left) => kleene(terms, right),
+            Node::Alternation(left, right) if is_epsilon(terms, &right) => kleene(terms, left),
+            node => {
+                let term = terms.make(node);
+                terms.make(Node::Kleene(term))
+            }
+        },
+        _ if is_null(terms, &term) => terms.make(Node::Epsilon),
+        _ => terms.make(Node::Kleene(term)),
+    }
+}
+
+/// Complement, where `!!a` is `a`, and the complement of the null set
+/// is `.*`, and the other way around.
+pub fn logical_not<T: Terms>(terms: &mut T, term: T::Term) -> T::Term {
+    if terms.kind(&term) == Kind::LogicalNot {
+        match terms.take(term) {
+            Node::LogicalNot(inner) => inner,
+            _ => unreachable!(),
+        }
+    } else if is_null(terms, &term) {
+        universal(terms)
+    } else if terms.is_universal(&term) {
+        null(terms)
+    } else {
+        terms.make(Node::LogicalNot(term))
+    }
+}
+
+/// Concatenation, nested to the right, as `a(bc)`.
+pub fn concatenate<T: Terms>(terms: &mut T, left: T::Term, right: T::Term) -> T::Term {
+    if is_null(terms, &left) || is_epsilon(terms, &right) {
+        left
+    } else if is_null(terms, &right) || is_epsilon(terms, &left) {
+        right
+    } else if terms.kind(&left) == Kind::Concatenation {
+        match terms.take(left) {
+            Node::Concatenation(first, rest) => {
+                let rest = concatenate(terms, rest, right);
+                terms.make(Node::Concatenation(first, rest))
+            }
+            _ => unreachable!(),
+        }
+    } else {
+        terms.make(Node::Concatenation(left, right))
+    }
+}
+
+/// Alternation / logical or operation
+///
+/// The arms are flattened, and all symbol sets are merged into one.
+/// The empty string is dropped when another arm is nullable, and
+/// `.*` absorbs all other arms.
+pub fn alternation<T: Terms>(terms: &mut T, left: T::Term, right: T::Term) -> T::Term {
+    let mut arms = vec![];
+    flatten(terms, left, Kind::Alternation, &mut arms);
+    flatten(terms, right, Kind::Alternation, &mut arms);
+
+    let mut symbols = CharSet::empty();
+    let mut others = vec![];
+    for arm in arms {
+        if terms.is_universal(&arm) {
+            return arm;
+        } else if let Some(s) = terms.symbols(&arm) {
+            symbols = symbols.union(s);
+        } else {
+            others.push(arm);
+        }
+    }
+    if !symbols.is_empty() {
+        others.push(terms.make(Node::SymbolSet(symbols)));
+    }
+    others.sort();
+    others.dedup();
+
+    // The empty string sorts first:
+    if others.len() > 1
+        && is_epsilon(terms, &others[0])
+        && others[1..].iter().any(|arm| terms.is_nullable(arm))
+    {
+        others.remove(0);
+    }
+    nest(terms, others, null, Node::Alternation)
+}
+
+/// Logical and operation
+///
+/// Like alternation, the arms are flattened and the symbol sets are
+/// merged. The null set absorbs all other arms, and `.*` is dropped.
+pub fn logical_and<T: Terms>(terms: &mut T, left: T::Term, right: T::Term) -> T::Term {
+    let mut arms = vec![];
+    flatten(terms, left, Kind::LogicalAnd, &mut arms);
+    flatten(terms, right, Kind::LogicalAnd, &mut arms);
+
+    let mut symbols: Option<CharSet> = None;
+    let mut others = vec![];
+    for arm in arms {
+        if terms.is_universal(&arm) {
+            continue;
+        } else if let Some(s) = terms.symbols(&arm) {
+            symbols = Some(match symbols {
+                Some(symbols) => symbols.intersection(s),
+                None => s.clone(),
+            });
+        } else {
+            others.push(arm);
+        }
+    }
+    if let Some(symbols) = symbols {
+        if symbols.is_empty() {
+            return null(terms);
+        }
+        others.push(terms.make(Node::SymbolSet(symbols)));
+    }
+    others.sort();
+    others.dedup();
+
+    // The empty string matches all arms, or nothing at all:
+    if others.iter().any(|arm| is_epsilon(terms, arm)) {
+        return if others.iter().all(|arm| terms.is_nullable(arm)) {
+            terms.make(Node::Epsilon)
+        } else {
+            null(terms)
+        };
+    }
+    nest(terms, others, universal, Node::LogicalAnd)
+}
+
+/// Collect the operands of nested nodes of the given kind.
+fn flatten<T: Terms>(terms: &T, term: T::Term, kind: Kind, arms: &mut Vec<T::Term>) {
+    if terms.kind(&term) == kind {
+        match terms.take(term) {
+            Node::Alternation(left, right) | Node::LogicalAnd(left, right) => {
+                flatten(terms, left, kind, arms);
+                flatten(terms, right, kind, arms);
+            }
+            _ => unreachable!(),
+        }
+    } else {
+        arms.push(term);
+    }
+}
+
+/// Combine the operands into a chain, nested to the right.
+fn nest<T: Terms>(
+    terms: &mut T,
+    mut operands: Vec<T::Term>,
+    empty: fn(&mut T) -> T::Term,
+    node: fn(T::Term, T::Term) -> Node<T::Term>,
+) -> T::Term {
+    match operands.pop() {
+        Some(last) => operands
+            .into_iter()
+            .rev()
+            .fold(last, |right, left| terms.make(node(left, right))),
+        None => empty(terms),
+    }
+}
@@ -1,35 +1,145 @@
-use crate::expression::Regex;
+//! Parser for the textual regex syntax.
+//!
+//! Operators, from lowest to highest precedence:
+//!
+//! - `a|b`: alternation
+//! - `a&b`: intersection, `a-b` and `a~b`: difference
+//! - `ab`: concatenation
+//! - `a*`, `a+`, `a?`, `a{m}`, `a{m,}`, `a{m,n}`: repetition, as long as
+//!   the expanded pattern stays below a size of 5000
+//! - `!a`: complement, which is a character set for a character set,
+//!   so `![ab]` is `[^ab]`, and the complement language otherwise
+//!
+//! Literal text can be quoted, like `".."`, in which only `\"` and `\\`
+//! and other escape sequences are special.
+//!
+//! Character classes can be written as `[a-z]`, `[^a-z]`, `[[:alpha:]]`,
+//! `\d`, `\w`, `\s` and their negations `\D`, `\W`, `\S`.
+//!
+//! Unicode properties are written as `\p{L}`, `\p{Script=Greek}` or `\pL`,
+//! and their negation as `\P{L}`.
+//!
+//! Code points can be written as `\xHH`, `\u{HHHHHH}` or in octal as `\ooo`.
+//!
+//! Named definitions are referred to as `{name}`, see `parse_regex_with`.
+//!
+//! Case insensitive matching is enabled for a group with `(?i:...)`, or
+//! with `(?i)` for the remainder of the enclosing group. Characters are
+//! compared using unicode simple case folding.
+//!
+//! Whitespace around the infix operators `|`, `&`, `-` and `~` is
+//! ignored, so `[a-z]+ - (if|else)` can be written. Elsewhere, whitespace
+//! matches itself.
+
+use crate::expression::{sigma, CharSet, Regex};
+use crate::range::Range;
+use crate::unicode::{case_fold_closure, property_class};
 
 pub fn parse_regex(txt: &str) -> Result<Regex, ParseError> {
-    let mut p = Parser::new(txt);
+    parse_regex_with(txt, &mut |name| Err(format!("Undefined name {}", name)))
+}
+
+/// Parse a regex, which may refer to named definitions, like `{digit}`.
+///
+/// The names are looked up using `resolve`, which gives the regex
+/// for a name, or an error message.
+pub fn parse_regex_with(txt: &str, resolve: &mut Resolve) -> Result<Regex, ParseError> {
+    let mut p = Parser::new(txt, resolve);
     p.parse()
 }
 
+pub type Resolve<'r> = dyn FnMut(&str) -> Result<Regex, String> + 'r;
+
+/// The largest size of a repetition, as a repetition is expanded into a
+/// copy of the regex for each count. Nested repetitions multiply, so the
+/// size of the expanded regex is limited, instead of each count.
+const MAX_REPETITION_SIZE: usize = 5000;
+
+/// Error in a regex pattern.
 #[derive(Debug)]
 pub struct ParseError {
     pub message: String,
-    pub index: usize,
+
+    /// Character indices of the offending part of the pattern.
+    pub span: std::ops::Range<usize>,
+
+    /// Descriptions of what would have been valid at this place.
+    pub expected: Vec<String>,
 }
 
-struct Parser {
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} at position {}", self.message, self.span.start + 1)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+struct Parser<'r> {
     index: usize,
     iter: Vec<char>,
+
+    /// Whether the `i` flag is in effect.
+    case_insensitive: bool,
+
+    resolve: &'r mut Resolve<'r>,
 }
 
 /// Regex parser.
 ///
 /// This thing can process a regex, one character at a time.
-impl Parser {
-    fn new(txt: &str) -> Self {
+impl<'r> Parser<'r> {
+    fn new(txt: &str, resolve: &'r mut Resolve<'r>) -> Self {
         Parser {
             index: 0,
             iter: txt.chars().collect(),
+            case_insensitive: false,
+            resolve,
         }
     }
 
     fn parse(&mut self) -> Result<Regex, ParseError> {
+        let expr = self.parse_alternation()?;
+        if let Some(c) = self.peek() {
+            // Only an unbalanced ) ends an expression early.
+            Err(self.error_at(
+                self.index,
+                format!("Unexpected {}, there is no matching (", c),
+            ))
+        } else {
+            Ok(expr)
+        }
+    }
+
+    /// Parse alternatives separated by `|`.
+    ///
+    /// Alternation has the lowest precedence of all operators.
+    fn parse_alternation(&mut self) -> Result<Regex, ParseError> {
+        let mut expr = self.parse_intersection()?;
+        while self.has_taken_operator(&['|']).is_some() {
+            expr = expr | self.parse_intersection()?;
+        }
+        Ok(expr)
+    }
+
+    /// Parse intersections (`&`) and differences (`-` or `~`).
+    ///
+    /// These operators are left associative, so `a-b-c` means `(a-b)-c`.
+    fn parse_intersection(&mut self) -> Result<Regex, ParseError> {
+        let mut expr = self.parse_concatenation()?;
+        loop {
+            match self.has_taken_operator(&['&', '-', '~']) {
+                Some('&') => expr = expr & self.parse_concatenation()?,
+                Some(_) => expr = expr - self.parse_concatenation()?,
+                None => break Ok(expr),
+            }
+        }
+    }
+
+    /// Parse a sequence of elements, each with optional postfix operators.
+    fn parse_concatenation(&mut self) -> Result<Regex, ParseError> {
         let mut expr = self.parse_one()?;
-        while self.peek().is_some() {
+        while !matches!(self.peek(), None | Some(')')) && self.peek_operator().is_none() {
             expr = expr + self.parse_one()?;
         }
         Ok(expr)
@@ -42,109 +152,483 @@ impl Parser {
     }
 
     fn parse_element(&mut self) -> Result<Regex, ParseError> {
-        let c = self.get_char()?;
+        let start = self.index;
+        let c = match self.peek() {
+            Some('|' | '&' | '-' | '~' | ')') | None => return Err(self.expected(&["expression"])),
+            Some(_) => self.get_char()?,
+        };
         match c {
-            '[' => {
-                // process ^ operator at start:
-                let inverted: bool = self.has_taken('^');
-
-                let mut ranges = vec![];
-
-                loop {
-                    let start = self.get_escaped_char()?;
-                    let range: (char, char) = if let Some('-') = self.peek() {
-                        self.get_char().expect("We have a character here");
-                        let end = self.get_escaped_char()?;
-                        (start, end)
-                    } else {
-                        (start, start)
-                    };
-                    ranges.push(range);
-
-                    if let Some(']') = self.peek() {
-                        break;
-                    }
-                }
-
-                // let symbol_set = CharSet::from_ranges(ranges);
-
-                self.take(']')?;
-
-                let regex = Regex::symbol_ranges(ranges);
-
-                if inverted {
-                    Ok(regex.logical_not())
-                } else {
-                    Ok(regex)
-                }
-            }
+            '[' => self.parse_class(),
             '(' => {
-                // braced expression
-                let mut elements = vec![];
-                loop {
-                    elements.push(self.parse_one()?);
-                    if let Some(')') = self.peek() {
-                        break;
+                // braced expression, flags set inside only apply up to the closing )
+                let case_insensitive = self.case_insensitive;
+                if self.has_taken('?') {
+                    self.parse_flags()?;
+                    if self.has_taken(')') {
+                        // Flags for the remainder of the enclosing group.
+                        return Ok(Regex::epsilon());
                     }
+                    self.take(':')?;
                 }
-
-                assert!(!elements.is_empty());
-                let (first, rest) = elements.split_first().unwrap();
-                let inner: Regex = rest.iter().fold(first.clone(), |a, b| a + b.clone());
+                let inner = self.parse_alternation()?;
                 self.take(')')?;
+                self.case_insensitive = case_insensitive;
                 Ok(inner)
             }
-            '!' => {
-                let inner = self.parse_element()?;
-                Ok(inner.logical_not())
-            }
+            '*' | '+' | '?' => Err(self.error_at(
+                start,
+                format!(
+                    "Unexpected {}, there is nothing to repeat, use \\{} to match a literal {}",
+                    c, c, c
+                ),
+            )),
+            '{' => self.parse_reference(start),
+            '!' => match self.parse_element()? {
+                // Like [^...], the complement of a set is the complementary set
+                Regex::SymbolSet(set) => Ok(Regex::SymbolSet(self.complement(&set))),
+                inner => Ok(inner.logical_not()),
+            },
             '.' => {
                 // any character
                 Ok(Regex::sigma())
             }
+            '"' => self.parse_literal(),
             '\\' => {
                 // handle escape character!
-                let c = Self::escape_it(self.get_char()?);
-                Ok(Regex::symbol(c))
+                match self.parse_escape()? {
+                    Escaped::Char(c) => Ok(self.fold_case(Regex::symbol(c))),
+                    Escaped::Class(set) => Ok(self.fold_case(Regex::SymbolSet(set))),
+                }
             }
-            c => Ok(Regex::symbol(c)),
+            c => Ok(self.fold_case(Regex::symbol(c))),
         }
     }
 
-    fn postfix(&mut self, expr: Regex) -> Result<Regex, ParseError> {
-        // parse suffix:
-        match self.peek() {
-            Some('*') => {
-                // zero or more
-                self.get_char().expect("We have a character here");
-                Ok(expr.kleene())
+    /// Parse a reference to a named definition, like `{digit}`,
+    /// after the opening brace.
+    fn parse_reference(&mut self, start: usize) -> Result<Regex, ParseError> {
+        let mut name = String::new();
+        loop {
+            match self.peek() {
+                Some('}') => break,
+                Some(c) if c.is_alphanumeric() || *c == '_' => {
+                    name.push(self.get_char()?);
+                }
+                _ => {
+                    return Err(self.error_at(
+                        start,
+                        "Unexpected {, expected a name like {digit}, use \\{ to match a literal {"
+                            .to_owned(),
+                    ))
+                }
+            }
+        }
+        self.take('}')?;
+
+        match (self.resolve)(&name) {
+            Ok(regex) => Ok(self.fold_case(regex)),
+            Err(message) => Err(self.error_at(start, message)),
+        }
+    }
+
+    /// Parse the flags of a group, like the `i` in `(?i:...)`.
+    fn parse_flags(&mut self) -> Result<(), ParseError> {
+        loop {
+            let start = self.index;
+            match self.peek() {
+                Some('i') => {
+                    self.get_char().expect("We have a character here");
+                    self.case_insensitive = true;
+                }
+                Some(':' | ')') => break Ok(()),
+                Some(_) => {
+                    let c = self.get_char().expect("We have a character here");
+                    return Err(
+                        self.error_at(start, format!("Unknown flag {}, only i is supported", c))
+                    );
+                }
+                None => return Err(self.expected(&["flag", ":", ")"])),
+            }
+        }
+    }
+
+    /// Apply the case insensitive flag, if it is in effect.
+    fn fold_case(&self, regex: Regex) -> Regex {
+        if self.case_insensitive {
+            regex.case_insensitive()
+        } else {
+            regex
+        }
+    }
+
+    /// Complement a set of characters.
+    ///
+    /// With the case insensitive flag, the set is folded before taking
+    /// the complement, such that `(?i:[^a])` does not match `A`.
+    fn complement(&self, set: &CharSet) -> CharSet {
+        if self.case_insensitive {
+            sigma().difference(&case_fold_closure(set))
+        } else {
+            sigma().difference(set)
+        }
+    }
+
+    /// Parse a quoted literal string, after the opening `"`.
+    fn parse_literal(&mut self) -> Result<Regex, ParseError> {
+        let mut text = String::new();
+        loop {
+            let start = self.index;
+            match self.peek() {
+                None => return Err(self.expected(&["\""])),
+                Some('"') => break,
+                Some('\\') => {
+                    self.get_char().expect("We have a character here");
+                    match self.parse_escape()? {
+                        Escaped::Char(c) => text.push(c),
+                        Escaped::Class(_) => {
+                            return Err(self.error_at(
+                                start,
+                                "A character class cannot be part of a literal".to_owned(),
+                            ))
+                        }
+                    }
+                }
+                Some(_) => text.push(self.get_char()?),
+            }
+        }
+        self.take('"')?;
+        Ok(self.fold_case(Regex::literal(&text)))
+    }
+
+    /// Parse postfix operators.
+    ///
+    /// Operators can be stacked, like `a{2}*`. A `?` or `+` directly
+    /// after another operator would be a lazy or possessive quantifier
+    /// in other regex dialects, which makes no sense for a scanner, so
+    /// it is rejected.
+    fn postfix(&mut self, mut expr: Regex) -> Result<Regex, ParseError> {
+        let mut quantified = false;
+        loop {
+            let start = self.index;
+            expr = match self.peek() {
+                Some('?' | '+') if quantified => {
+                    let c = self.get_char().expect("We have a character here");
+                    return Err(self.error_at(start, format!(
+                        "Unexpected {} after a repetition, lazy or possessive repetition is not supported",
+                        c
+                    )));
+                }
+                Some('*') => {
+                    // zero or more
+                    self.get_char().expect("We have a character here");
+                    expr.kleene()
+                }
+                Some('+') => {
+                    // one or more.
+                    self.get_char().expect("We have a character here");
+                    expr.one_or_more()
+                }
+                Some('?') => {
+                    // zero or one.
+                    self.get_char().expect("We have a character here");
+                    expr.optional()
+                }
+                Some('{') if matches!(self.iter.get(self.index + 1), Some(c) if c.is_ascii_digit() || *c == ',') =>
+                {
+                    // counted repetition, otherwise this is a reference to a definition
+                    self.get_char().expect("We have a character here");
+                    let (min, max) = self.parse_counts(start)?;
+
+                    // Each count gives a copy of the regex, in a concatenation
+                    // and for optional counts an alternation with the empty string.
+                    let copies = max.unwrap_or(min + 1);
+                    let size = copies.saturating_mul(size(&expr) + 3);
+                    if size > MAX_REPETITION_SIZE {
+                        return Err(self.error_at(
+                            start,
+                            format!(
+                                "Repetition is too large, as it expands to a size of {}, the maximum is {}",
+                                size, MAX_REPETITION_SIZE
+                            ),
+                        ));
+                    }
+                    expr.repeat(min, max)
+                }
+                _ => break Ok(expr),
+            };
+            quantified = true;
+        }
+    }
+
+    /// Parse the inside of `{m}`, `{m,}` or `{m,n}`, after the opening brace.
+    fn parse_counts(&mut self, start: usize) -> Result<(usize, Option<usize>), ParseError> {
+        if let Some(',') = self.peek() {
+            return Err(self.error_at(
+                start,
+                "A repetition needs a minimum count, like {0,3}".to_owned(),
+            ));
+        }
+        let min = self.parse_number()?;
+        let max = if self.has_taken(',') {
+            if let Some('}') = self.peek() {
+                None
+            } else {
+                Some(self.parse_number()?)
             }
-            Some('+') => {
-                // one or more.
+        } else {
+            Some(min)
+        };
+        self.take('}')?;
+
+        match max {
+            Some(max) if max < min => Err(self.error_at(
+                start,
+                format!(
+                    "Invalid repetition {{{},{}}}, maximum is smaller than minimum",
+                    min, max
+                ),
+            )),
+            _ => Ok((min, max)),
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<usize, ParseError> {
+        let start = self.index;
+        let mut digits = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_ascii_digit() {
+                digits.push(*c);
                 self.get_char().expect("We have a character here");
-                Ok(expr.one_or_more())
+            } else {
+                break;
             }
-            Some('{') => {
-                unimplemented!();
+        }
+
+        if digits.is_empty() {
+            Err(self.expected(&["number"]))
+        } else {
+            match digits.parse() {
+                Ok(count) => Ok(count),
+                Err(_) => {
+                    Err(self.error_at(start, format!("Repetition count {} is too large", digits)))
+                }
             }
-            _ => Ok(expr),
         }
     }
 
-    fn escape_it(c: char) -> char {
-        match c {
-            'n' => '\n',
-            other => other,
+    /// Parse a bracket expression, after the opening `[`.
+    ///
+    /// A `]` directly after the opening bracket, and a `-` at the end, are
+    /// taken literally.
+    fn parse_class(&mut self) -> Result<Regex, ParseError> {
+        // process ^ operator at start:
+        let inverted: bool = self.has_taken('^');
+
+        let mut set = CharSet::empty();
+        let mut first = true;
+        loop {
+            match self.peek() {
+                None => return Err(self.expected(&["]"])),
+                Some(']') if !first => break,
+                Some('[') if self.iter.get(self.index + 1) == Some(&':') => {
+                    set = set.union(&self.parse_posix_class()?);
+                }
+                _ => match self.parse_class_item()? {
+                    (_, Escaped::Class(class)) => {
+                        set = set.union(&class);
+                    }
+                    (start, Escaped::Char(begin)) => {
+                        let end = if self.peek() == Some(&'-')
+                            && !matches!(self.iter.get(self.index + 1), None | Some(']'))
+                        {
+                            self.get_char().expect("We have a character here");
+                            match self.parse_class_item()? {
+                                (_, Escaped::Char(end)) => end,
+                                (end_start, Escaped::Class(_)) => {
+                                    return Err(self.error_at(
+                                        end_start,
+                                        "A character class cannot end a range".to_owned(),
+                                    ))
+                                }
+                            }
+                        } else {
+                            begin
+                        };
+
+                        if end < begin {
+                            return Err(
+                                self.error_at(start, format!("Invalid range {}-{}", begin, end))
+                            );
+                        }
+                        set = set.union(&CharSet::new2(begin, end));
+                    }
+                },
+            }
+            first = false;
         }
+
+        self.take(']')?;
+
+        if inverted {
+            set = self.complement(&set);
+        }
+        Ok(self.fold_case(Regex::SymbolSet(set)))
     }
 
-    fn get_escaped_char(&mut self) -> Result<char, ParseError> {
+    /// Parse a single character or escape sequence inside a bracket expression.
+    ///
+    /// Returns the start index of the item, along with the item.
+    fn parse_class_item(&mut self) -> Result<(usize, Escaped), ParseError> {
+        let start = self.index;
         let c = self.get_char()?;
         if c == '\\' {
-            let c = self.get_char()?;
-            Ok(Self::escape_it(c))
+            Ok((start, self.parse_escape()?))
         } else {
-            Ok(c)
+            Ok((start, Escaped::Char(c)))
+        }
+    }
+
+    /// Parse a POSIX class, such as `[:alpha:]`, inside a bracket expression.
+    fn parse_posix_class(&mut self) -> Result<CharSet, ParseError> {
+        let start = self.index;
+        self.take('[')?;
+        self.take(':')?;
+        let mut name = String::new();
+        loop {
+            match self.get_char()? {
+                ':' => break,
+                c => name.push(c),
+            }
+        }
+        self.take(']')?;
+        posix_class(&name)
+            .ok_or_else(|| self.error_at(start, format!("Unknown class [:{}:]", name)))
+    }
+
+    /// Parse an escape sequence, after the backslash.
+    fn parse_escape(&mut self) -> Result<Escaped, ParseError> {
+        let start = self.index - 1;
+        let c = self.get_char()?;
+        let escaped = match c {
+            'n' => Escaped::Char('\n'),
+            't' => Escaped::Char('\t'),
+            'r' => Escaped::Char('\r'),
+            'f' => Escaped::Char('\u{c}'),
+            'v' => Escaped::Char('\u{b}'),
+            '0'..='7' => Escaped::Char(self.parse_octal(start, c)?),
+            'x' => {
+                let mut digits = String::new();
+                for _ in 0..2 {
+                    digits.push(self.get_char()?);
+                }
+                Escaped::Char(self.code_point(start, &digits, 16)?)
+            }
+            'u' => {
+                let digits = self.parse_braced()?;
+                if digits.len() > 6 {
+                    return Err(
+                        self.error_at(start, format!("Code point {} has too many digits", digits))
+                    );
+                }
+                Escaped::Char(self.code_point(start, &digits, 16)?)
+            }
+            'd' | 'w' | 's' => Escaped::Class(perl_class(c)),
+            'D' | 'W' | 'S' => Escaped::Class(self.complement(&perl_class(c.to_ascii_lowercase()))),
+            'p' | 'P' => {
+                // A single letter property, like \pL, or a braced one, like \p{Greek}
+                let property = if let Some('{') = self.peek() {
+                    self.parse_braced()?
+                } else {
+                    self.get_char()?.to_string()
+                };
+                let set = property_class(&property).ok_or_else(|| {
+                    self.error_at(start, format!("Unknown unicode property {}", property))
+                })?;
+                if c == 'P' {
+                    Escaped::Class(self.complement(&set))
+                } else {
+                    Escaped::Class(set)
+                }
+            }
+            other => Escaped::Char(other),
+        };
+        Ok(escaped)
+    }
+
+    /// Parse text between `{` and `}`.
+    fn parse_braced(&mut self) -> Result<String, ParseError> {
+        self.take('{')?;
+        let mut text = String::new();
+        loop {
+            match self.get_char()? {
+                '}' => break Ok(text),
+                c => text.push(c),
+            }
+        }
+    }
+
+    /// Parse up to three octal digits, the first of which is already taken.
+    fn parse_octal(&mut self, start: usize, first: char) -> Result<char, ParseError> {
+        let mut digits = first.to_string();
+        while digits.len() < 3 {
+            match self.peek() {
+                Some(c @ '0'..='7') => {
+                    digits.push(*c);
+                    self.get_char().expect("We have a character here");
+                }
+                _ => break,
+            }
+        }
+        self.code_point(start, &digits, 8)
+    }
+
+    /// Convert digits into a unicode scalar value.
+    ///
+    /// The escape sequence of the digits starts at `start`.
+    fn code_point(&self, start: usize, digits: &str, radix: u32) -> Result<char, ParseError> {
+        if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+            return Err(self.error_at(start, format!("Invalid code point {}", digits)));
+        }
+        let value = u32::from_str_radix(digits, radix).expect("Digits are validated");
+        if (0xD800..=0xDFFF).contains(&value) {
+            Err(self.error_at(
+                start,
+                format!(
+                    "Code point {:X} is a surrogate, which is not allowed",
+                    value
+                ),
+            ))
+        } else {
+            std::char::from_u32(value).ok_or_else(|| {
+                self.error_at(start, format!("Code point {:X} is out of range", value))
+            })
+        }
+    }
+
+    /// Find the infix operator at the current position, which may
+    /// be preceded by whitespace.
+    fn peek_operator(&self) -> Option<(char, usize)> {
+        let mut index = self.index;
+        while matches!(self.iter.get(index), Some(c) if c.is_whitespace()) {
+            index += 1;
+        }
+        match self.iter.get(index) {
+            Some(c @ ('|' | '&' | '-' | '~')) => Some((*c, index)),
+            _ => None,
+        }
+    }
+
+    /// Take one of the given infix operators, and the whitespace around it.
+    fn has_taken_operator(&mut self, operators: &[char]) -> Option<char> {
+        match self.peek_operator() {
+            Some((c, index)) if operators.contains(&c) => {
+                self.index = index + 1;
+                while matches!(self.peek(), Some(c) if c.is_whitespace()) {
+                    self.index += 1;
+                }
+                Some(c)
+            }
+            _ => None,
         }
     }
 
@@ -173,31 +657,98 @@ impl Parser {
 
         match c {
             Some(c) => Ok(c),
-            None => Err(self.error("Expected character".to_owned())),
+            None => Err(self.error_at(self.index, "Unexpected end of pattern".to_owned())),
         }
     }
 
     /// Expect the given character, and proceed
     fn take(&mut self, expected_c: char) -> Result<(), ParseError> {
-        let c = self.get_char()?;
-        if c == expected_c {
+        if self.has_taken(expected_c) {
             Ok(())
         } else {
-            Err(self.error(format!("Expected {}, but got {}", expected_c, c)))
+            Err(self.expected(&[&expected_c.to_string()]))
         }
     }
 
-    fn error(&self, message: String) -> ParseError {
+    /// Create an error at the current character, listing what was expected instead.
+    fn expected(&self, expected: &[&str]) -> ParseError {
+        let got = match self.peek() {
+            Some(c) => c.to_string(),
+            None => "end of pattern".to_owned(),
+        };
+        ParseError {
+            message: format!("Expected {}, but got {}", expected.join(" or "), got),
+            span: self.index..self.index + 1,
+            expected: expected.iter().map(|e| e.to_string()).collect(),
+        }
+    }
+
+    /// Create an error spanning from `start` up to the current position.
+    fn error_at(&self, start: usize, message: String) -> ParseError {
         ParseError {
             message,
-            index: self.index,
+            span: start..self.index.max(start + 1),
+            expected: vec![],
         }
     }
 }
 
+/// Count the operators and character sets in a regex.
+fn size(regex: &Regex) -> usize {
+    match regex {
+        Regex::Epsilon | Regex::SymbolSet(_) => 1,
+        Regex::Kleene(r) | Regex::LogicalNot(r) => 1 + size(r),
+        Regex::Alternation { left, right }
+        | Regex::Concatenation { left, right }
+        | Regex::LogicalAnd { left, right } => 1 + size(left) + size(right),
+    }
+}
+
+/// Result of an escape sequence.
+enum Escaped {
+    Char(char),
+    Class(CharSet),
+}
+
+/// Character classes `\d`, `\w` and `\s`.
+fn perl_class(c: char) -> CharSet {
+    let ranges = match c {
+        'd' => vec![('0', '9')],
+        'w' => vec![('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')],
+        's' => vec![(' ', ' '), ('\t', '\r')],
+        _ => unreachable!(),
+    };
+    from_ranges(ranges)
+}
+
+/// Look up a POSIX character class by name.
+fn posix_class(name: &str) -> Option<CharSet> {
+    let ranges = match name {
+        "alnum" => vec![('0', '9'), ('A', 'Z'), ('a', 'z')],
+        "alpha" => vec![('A', 'Z'), ('a', 'z')],
+        "blank" => vec![(' ', ' '), ('\t', '\t')],
+        "cntrl" => vec![('\0', '\u{1f}'), ('\u{7f}', '\u{7f}')],
+        "digit" => vec![('0', '9')],
+        "graph" => vec![('!', '~')],
+        "lower" => vec![('a', 'z')],
+        "print" => vec![(' ', '~')],
+        "punct" => vec![('!', '/'), (':', '@'), ('[', '`'), ('{', '~')],
+        "space" => vec![(' ', ' '), ('\t', '\r')],
+        "upper" => vec![('A', 'Z')],
+        "word" => vec![('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')],
+        "xdigit" => vec![('0', '9'), ('A', 'F'), ('a', 'f')],
+        _ => return None,
+    };
+    Some(from_ranges(ranges))
+}
+
+fn from_ranges(ranges: Vec<(char, char)>) -> CharSet {
+    CharSet::from_ranges(ranges.into_iter().map(|r| Range::new(r.0, r.1)).collect())
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{parse_regex, Regex};
+    use super::{parse_regex, parse_regex_with, Regex};
 
     #[test]
     fn parse_symbol() {
@@ -219,4 +770,368 @@ mod tests {
         let expr2 = Regex::symbol_ranges(vec![('B', 'L'), ('X', 'X')]);
         assert_eq!(expr, expr2);
     }
+
+    #[test]
+    fn parse_alternation() {
+        let expr = parse_regex("ab|cd").unwrap();
+        let expr2 =
+            (Regex::symbol('a') + Regex::symbol('b')) | (Regex::symbol('c') + Regex::symbol('d'));
+        assert_eq!(expr, expr2);
+    }
+
+    #[test]
+    fn parse_alternation_in_group() {
+        let expr = parse_regex("(0x[0-9a-f]+|[0-9]+)").unwrap();
+        let hex = Regex::symbol('0')
+            + Regex::symbol('x')
+            + Regex::symbol_ranges(vec![('0', '9'), ('a', 'f')]).one_or_more();
+        let dec = Regex::symbol_range('0', '9').one_or_more();
+        assert_eq!(expr, hex | dec);
+    }
+
+    #[test]
+    fn parse_counted_repetition() {
+        let a = Regex::symbol('a');
+        assert_eq!(
+            parse_regex("a{3}").unwrap(),
+            a.clone() + (a.clone() + a.clone())
+        );
+        assert_eq!(
+            parse_regex("a{2,}").unwrap(),
+            a.clone() + (a.clone() + a.clone().kleene())
+        );
+        assert_eq!(
+            parse_regex("a{1,3}").unwrap(),
+            a.clone() + ((a.clone() + (a.clone() | Regex::epsilon())) | Regex::epsilon())
+        );
+        assert_eq!(parse_regex("a{0}").unwrap(), Regex::epsilon());
+        assert!(parse_regex("a{1000}").is_ok());
+    }
+
+    #[test]
+    fn parse_repetition_limit() {
+        assert!(parse_regex("a{1000}").is_ok());
+        assert!(parse_regex("[a-z]{0,1000}").is_ok());
+
+        let err = parse_regex("a{100000000}").unwrap_err();
+        assert_eq!(
+            err.message,
+            "Repetition is too large, as it expands to a size of 400000000, the maximum is 5000"
+        );
+        assert_eq!(err.span, 1..12);
+        let err = parse_regex("a{1,99999999}").unwrap_err();
+        assert_eq!(err.span, 1..13);
+        let err = parse_regex("a{99999999999999999999999}").unwrap_err();
+        assert_eq!(
+            err.message,
+            "Repetition count 99999999999999999999999 is too large"
+        );
+        assert_eq!(err.span, 2..25);
+
+        // Nested repetitions multiply.
+        assert!(parse_regex("(a{10}){10}").is_ok());
+        let err = parse_regex("(a{1000}){200}").unwrap_err();
+        assert_eq!(err.span, 9..14);
+    }
+
+    #[test]
+    fn parse_counted_repetition_errors() {
+        assert!(parse_regex("a{3,1}").is_err());
+        assert!(parse_regex("a{x}").is_err());
+        assert!(parse_regex("a{3").is_err());
+        assert!(parse_regex("{a}").is_err());
+        assert!(parse_regex("a{ 3}").is_err());
+        let err = parse_regex("a{,3}").unwrap_err();
+        assert_eq!(
+            err.message,
+            "A repetition needs a minimum count, like {0,3}"
+        );
+        assert!(parse_regex("\\{a}").is_ok());
+    }
+
+    #[test]
+    fn parse_optional() {
+        let expr = parse_regex("[0-9]+(e[0-9]+)?").unwrap();
+        let digits = Regex::symbol_range('0', '9').one_or_more();
+        let exponent = Regex::symbol('e') + digits.clone();
+        assert_eq!(expr, digits + (exponent | Regex::epsilon()));
+    }
+
+    #[test]
+    fn parse_stacked_postfix() {
+        let a = Regex::symbol('a');
+        assert_eq!(parse_regex("a**").unwrap(), a.clone().kleene());
+        assert_eq!(parse_regex("a?*").unwrap(), a.clone().kleene());
+        assert_eq!(parse_regex("a{2}*").unwrap(), (a.clone() + a).kleene());
+        assert!(parse_regex("a+?").is_err());
+        assert!(parse_regex("a*+").is_err());
+        assert!(parse_regex("a??").is_err());
+        assert!(parse_regex("?a").is_err());
+    }
+
+    #[test]
+    fn parse_intersection() {
+        let expr = parse_regex("[a-z]+&x.*").unwrap();
+        let word = Regex::symbol_range('a', 'z').one_or_more();
+        let x_first = Regex::symbol('x') + Regex::sigma().kleene();
+        assert_eq!(expr, word & x_first);
+    }
+
+    #[test]
+    fn parse_difference() {
+        let expr = parse_regex("[a-z]+ - (if|else)").unwrap();
+        assert_eq!(expr, parse_regex("[a-z]+~(if|else)").unwrap());
+        assert!(expr.matches("iffy"));
+        assert!(expr.matches("els"));
+        assert!(!expr.matches("if"));
+        assert!(!expr.matches("else"));
+
+        // Difference with a single character removes that word only:
+        let expr = parse_regex("[a-z]+ - x").unwrap();
+        assert!(expr.matches("xx"));
+        assert!(!expr.matches("x"));
+    }
+
+    #[test]
+    fn parse_c_comment() {
+        let expr = parse_regex("/\\*(!(.*\\*/.*))\\*/").unwrap();
+        assert!(expr.matches("/* comment */"));
+        assert!(expr.matches("/**/"));
+        assert!(!expr.matches("/* a */ b */"));
+        assert!(!expr.matches("/* a"));
+    }
+
+    #[test]
+    fn parse_whitespace() {
+        // Whitespace matches itself, except around infix operators.
+        let expr = parse_regex("a b").unwrap();
+        assert!(expr.matches("a b"));
+        assert!(!expr.matches("ab"));
+        assert_eq!(parse_regex(" a").unwrap(), parse_regex("\\ a").unwrap());
+        assert_eq!(parse_regex("(a )").unwrap(), parse_regex("a\\ ").unwrap());
+
+        assert_eq!(
+            parse_regex("a b | c").unwrap(),
+            parse_regex("a\\ b|c").unwrap()
+        );
+        assert_eq!(
+            parse_regex("[a-z]+ - x & y* ~ z").unwrap(),
+            parse_regex("[a-z]+-x&y*~z").unwrap()
+        );
+        assert_eq!(
+            parse_regex("a\\  - b").unwrap(),
+            parse_regex("a\\ -b").unwrap()
+        );
+        assert!(parse_regex("[ ]").unwrap().matches(" "));
+    }
+
+    #[test]
+    fn parse_complement() {
+        // The complement of a set is a set, like a negated class:
+        assert_eq!(parse_regex("![ab]").unwrap(), parse_regex("[^ab]").unwrap());
+        assert_eq!(parse_regex("!\\d").unwrap(), parse_regex("\\D").unwrap());
+        let expr = parse_regex("!a").unwrap();
+        assert!(expr.matches("b"));
+        assert!(!expr.matches("a"));
+        assert!(!expr.matches("bb"));
+        assert!(!expr.matches(""));
+
+        // Otherwise, it is the complement language:
+        let expr = parse_regex("!(ab)").unwrap();
+        assert!(expr.matches("a"));
+        assert!(expr.matches("bb"));
+        assert!(expr.matches(""));
+        assert!(!expr.matches("ab"));
+    }
+
+    #[test]
+    fn parse_class_edge_cases() {
+        assert_eq!(
+            parse_regex("[]a]").unwrap(),
+            Regex::symbol_ranges(vec![(']', ']'), ('a', 'a')])
+        );
+        assert_eq!(
+            parse_regex("[a-]").unwrap(),
+            Regex::symbol_ranges(vec![('-', '-'), ('a', 'a')])
+        );
+        assert!(parse_regex("[z-a]").is_err());
+        assert!(parse_regex("[a-\\d]").is_err());
+        assert!(parse_regex("[abc").is_err());
+    }
+
+    #[test]
+    fn parse_escapes() {
+        let expr = parse_regex("\\t\\r\\0\\f\\v").unwrap();
+        assert!(expr.matches("\t\r\0\u{c}\u{b}"));
+    }
+
+    #[test]
+    fn parse_code_points() {
+        assert_eq!(parse_regex("\\x41").unwrap(), Regex::symbol('A'));
+        assert_eq!(parse_regex("\\u{41}").unwrap(), Regex::symbol('A'));
+        assert_eq!(
+            parse_regex("\\u{1F600}").unwrap(),
+            Regex::symbol('\u{1F600}')
+        );
+        assert_eq!(parse_regex("\\101").unwrap(), Regex::symbol('A'));
+        assert_eq!(parse_regex("\\0").unwrap(), Regex::symbol('\0'));
+        assert_eq!(
+            parse_regex("[\\x00-\\u{10FFFF}]").unwrap(),
+            Regex::symbol_range('\0', '\u{10FFFF}')
+        );
+        assert_eq!(
+            parse_regex("\\0123").unwrap(),
+            Regex::symbol('\n') + Regex::symbol('3')
+        );
+    }
+
+    #[test]
+    fn parse_code_point_errors() {
+        assert!(parse_regex("\\u{D800}").is_err());
+        assert!(parse_regex("\\u{DFFF}").is_err());
+        assert!(parse_regex("\\u{110000}").is_err());
+        assert!(parse_regex("\\u{0000041}").is_err());
+        assert!(parse_regex("\\u{}").is_err());
+        assert!(parse_regex("\\u41").is_err());
+        assert!(parse_regex("\\xG1").is_err());
+        assert!(parse_regex("\\x+1").is_err());
+        assert!(parse_regex("[\\x4]").is_err());
+    }
+
+    #[test]
+    fn parse_class_escapes() {
+        assert_eq!(parse_regex("\\d").unwrap(), Regex::symbol_range('0', '9'));
+        assert_eq!(
+            parse_regex("[\\d_]").unwrap(),
+            Regex::symbol_ranges(vec![('0', '9'), ('_', '_')])
+        );
+        let word = parse_regex("\\w+").unwrap();
+        assert!(word.matches("foo_42"));
+        assert!(!word.matches("foo-42"));
+        let not_space = parse_regex("\\S").unwrap();
+        assert!(not_space.matches("x"));
+        assert!(!not_space.matches(" "));
+        assert!(parse_regex("\\s").unwrap().matches("\t"));
+    }
+
+    #[test]
+    fn parse_unicode_properties() {
+        let identifier = parse_regex("\\p{XID_Start}\\p{XID_Continue}*").unwrap();
+        assert!(identifier.matches("abc"));
+        assert!(identifier.matches("λx_1"));
+        assert!(!identifier.matches("1x"));
+        assert_eq!(parse_regex("\\pL").unwrap(), parse_regex("\\p{L}").unwrap());
+        assert!(parse_regex("[\\p{Greek}\\d]").unwrap().matches("λ"));
+        assert!(parse_regex("[\\p{Greek}\\d]").unwrap().matches("7"));
+        let not_digit = parse_regex("\\P{Nd}").unwrap();
+        assert!(not_digit.matches("a"));
+        assert!(!not_digit.matches("1"));
+        let other = parse_regex("\\p{C}").unwrap();
+        assert!(other.matches("\u{E000}"));
+        assert!(parse_regex("\\p{Foo}").is_err());
+        assert!(parse_regex("\\p{L").is_err());
+    }
+
+    #[test]
+    fn parse_posix_classes() {
+        assert_eq!(
+            parse_regex("[[:xdigit:]]").unwrap(),
+            Regex::symbol_ranges(vec![('0', '9'), ('A', 'F'), ('a', 'f')])
+        );
+        assert_eq!(
+            parse_regex("[[:alpha:]_]").unwrap(),
+            Regex::symbol_ranges(vec![('A', 'Z'), ('_', '_'), ('a', 'z')])
+        );
+        let expr = parse_regex("[^[:digit:]]").unwrap();
+        assert!(expr.matches("a"));
+        assert!(!expr.matches("5"));
+        assert!(parse_regex("[[:foo:]]").is_err());
+    }
+
+    #[test]
+    fn parse_literal() {
+        assert_eq!(parse_regex("\"..\"").unwrap(), Regex::literal(".."));
+        assert_eq!(
+            parse_regex("\"a b\"+").unwrap(),
+            Regex::literal("a b").one_or_more()
+        );
+        assert_eq!(
+            parse_regex("\"\\\"\\\\\\n\"").unwrap(),
+            Regex::literal("\"\\\n")
+        );
+        assert_eq!(parse_regex("\"\"").unwrap(), Regex::epsilon());
+        assert_eq!(
+            parse_regex("\"{\"[^}]*\"}\"").unwrap(),
+            parse_regex("\\{[^}]*\\}").unwrap()
+        );
+        assert!(parse_regex("\"abc").is_err());
+        assert!(parse_regex("\"\\d\"").is_err());
+    }
+
+    #[test]
+    fn parse_reference() {
+        let digit = Regex::symbol_range('0', '9');
+        let mut resolve = |name: &str| match name {
+            "digit" => Ok(digit.clone()),
+            _ => Err(format!("Undefined name {}", name)),
+        };
+        assert_eq!(
+            parse_regex_with("{digit}+", &mut resolve).unwrap(),
+            digit.clone().one_or_more()
+        );
+        assert_eq!(
+            parse_regex_with("x{digit}{2}", &mut resolve).unwrap(),
+            Regex::symbol('x') + digit.clone().repeat(2, Some(2))
+        );
+        assert_eq!(
+            parse_regex_with("(?i:a{digit})", &mut resolve).unwrap(),
+            Regex::symbol_ranges(vec![('A', 'A'), ('a', 'a')]) + digit.clone()
+        );
+
+        let err = parse_regex_with("a|{letter}", &mut resolve).unwrap_err();
+        assert_eq!(err.message, "Undefined name letter");
+        assert_eq!(err.span, 2..10);
+        assert!(parse_regex_with("{dig it}", &mut resolve).is_err());
+        assert!(parse_regex_with("{digit", &mut resolve).is_err());
+    }
+
+    #[test]
+    fn parse_case_insensitive() {
+        let expr = parse_regex("(?i:begin)").unwrap();
+        assert!(expr.matches("BEGIN"));
+        assert!(expr.matches("Begin"));
+
+        let expr = parse_regex("a(?i:b)c").unwrap();
+        assert!(expr.matches("aBc"));
+        assert!(!expr.matches("ABc"));
+        assert!(!expr.matches("aBC"));
+
+        let expr = parse_regex("a((?i)b)c|d").unwrap();
+        assert!(expr.matches("aBc"));
+        assert!(!expr.matches("D"));
+
+        let expr = parse_regex("(?i)end|\"x\"|[^a]").unwrap();
+        assert!(expr.matches("END"));
+        assert!(expr.matches("X"));
+        assert!(expr.matches("b"));
+        assert!(!expr.matches("A"));
+
+        // The long s folds to s, which is a word character.
+        let expr = parse_regex("(?i:\\W)").unwrap();
+        assert!(!expr.matches("\u{17F}"));
+        assert!(!expr.matches("S"));
+
+        assert_eq!(parse_regex("(?:ab)").unwrap(), parse_regex("ab").unwrap());
+        assert!(parse_regex("(?x:ab)").is_err());
+        assert!(parse_regex("(?i").is_err());
+    }
+
+    #[test]
+    fn parse_empty_alternative() {
+        assert!(parse_regex("a|").is_err());
+        assert!(parse_regex("(|a)").is_err());
+        assert!(parse_regex("a)").is_err());
+        assert!(parse_regex("-a").is_err());
+        assert!(parse_regex("a&").is_err());
+    }
 }
@@ -0,0 +1,312 @@
+//! Printer for the textual regex syntax.
+//!
+//! This is the inverse of the parser: the printed pattern can be parsed
+//! again. For a regex built with the constructors of `Regex`, parsing the
+//! printed pattern gives back an equal regex.
+//!
+//! Parentheses are only emitted where the precedence of the operators
+//! requires them. Chains of alternations, intersections and concatenations
+//! are nested to the right by the constructors, and printed without
+//! parentheses, as these operators are associative.
+
+use crate::expression::{sigma, CharSet, Regex};
+use crate::range::{ItemsBetween, Range};
+use crate::unicode::is_graphic;
+use std::fmt::Write;
+
+/// Print a regex as a pattern, which can be read by `parse_regex`.
+pub fn print_regex(regex: &Regex) -> String {
+    let mut text = String::new();
+    print(regex, Precedence::Alternation, &mut text);
+    text
+}
+
+/// Operator precedence, from lowest to highest.
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+enum Precedence {
+    Alternation,
+    Intersection,
+    Concatenation,
+    Postfix,
+    Prefix,
+    Atom,
+}
+
+fn precedence(regex: &Regex) -> Precedence {
+    match regex {
+        Regex::Epsilon | Regex::SymbolSet(_) => Precedence::Atom,
+        Regex::Alternation { left, right } if left.is_epsilon() && !right.is_nullable() => {
+            Precedence::Postfix
+        }
+        Regex::Alternation { .. } => Precedence::Alternation,
+        Regex::LogicalAnd { .. } => Precedence::Intersection,
+        Regex::Concatenation { left, right } if is_kleene_of(right, left) => Precedence::Postfix,
+        Regex::Concatenation { .. } => Precedence::Concatenation,
+        Regex::Kleene(_) => Precedence::Postfix,
+        Regex::LogicalNot(r) if matches!(r.as_ref(), Regex::SymbolSet(_)) => {
+            Precedence::Intersection
+        }
+        Regex::LogicalNot(_) => Precedence::Prefix,
+    }
+}
+
+/// Test if `closure` is the Kleene closure of `regex`, such that
+/// `regex closure` can be printed as `regex+`.
+fn is_kleene_of(closure: &Regex, regex: &Regex) -> bool {
+    matches!(closure, Regex::Kleene(r) if r.as_ref() == regex)
+}
+
+/// Print a regex, in parentheses if it binds less tight than `minimum`.
+fn print(regex: &Regex, minimum: Precedence, text: &mut String) {
+    if precedence(regex) < minimum {
+        text.push('(');
+        print(regex, Precedence::Alternation, text);
+        text.push(')');
+        return;
+    }
+
+    // Operands of postfix and prefix operators are elements. Nested
+    // postfix operators are parenthesized, as `a*?` would be taken as
+    // a lazy quantifier.
+    match regex {
+        Regex::Epsilon => text.push_str("\"\""),
+        Regex::SymbolSet(set) => print_set(set, text),
+        Regex::Alternation { left, right } if precedence(regex) == Precedence::Postfix => {
+            // Made optional, like with `optional`, the empty string sorts first
+            debug_assert!(left.is_epsilon());
+            print(right, Precedence::Prefix, text);
+            text.push('?');
+        }
+        Regex::Alternation { left, right } => {
+            print(left, Precedence::Intersection, text);
+            text.push('|');
+            print(right, Precedence::Alternation, text);
+        }
+        Regex::LogicalAnd { left, right } => {
+            print(left, Precedence::Concatenation, text);
+            if let Regex::LogicalNot(right) = right.as_ref() {
+                text.push('-');
+                print(right, Precedence::Concatenation, text);
+            } else {
+                text.push('&');
+                print(right, Precedence::Intersection, text);
+            }
+        }
+        Regex::Concatenation { left, right } if precedence(regex) == Precedence::Postfix => {
+            // One or more, like with `one_or_more`
+            debug_assert!(is_kleene_of(right, left));
+            print(left, Precedence::Prefix, text);
+            text.push('+');
+        }
+        Regex::Concatenation { left, right } => {
+            print(left, Precedence::Postfix, text);
+            print(right, Precedence::Concatenation, text);
+        }
+        Regex::Kleene(r) => {
+            print(r, Precedence::Prefix, text);
+            text.push('*');
+        }
+        Regex::LogicalNot(r) if precedence(regex) == Precedence::Intersection => {
+            // The ! operator gives the complementary set for a set
+            text.push_str(".*-");
+            print(r, Precedence::Concatenation, text);
+        }
+        Regex::LogicalNot(r) => {
+            text.push('!');
+            print(r, Precedence::Prefix, text);
+        }
+    }
+}
+
+fn print_set(set: &CharSet, text: &mut String) {
+    if set.ranges.len() == 1 && set.cardinality() == 1 {
+        escape(set.first(), text);
+    } else if *set == sigma() {
+        text.push('.');
+    } else {
+        // Use the negated class when that is shorter, which includes the
+        // empty set, which is printed as the complement of all characters.
+        let complement = sigma().difference(set);
+        text.push('[');
+        let ranges = if complement.ranges.len() < set.ranges.len() || set.is_empty() {
+            text.push('^');
+            &complement.ranges
+        } else {
+            &set.ranges
+        };
+        for range in ranges {
+            print_range(range, text);
+        }
+        text.push(']');
+    }
+}
+
+fn print_range(range: &Range<char>, text: &mut String) {
+    escape_in_class(range.begin, text);
+    match range.begin.items_between(&range.end) {
+        1 => {}
+        2 => escape_in_class(range.end, text),
+        _ => {
+            text.push('-');
+            escape_in_class(range.end, text);
+        }
+    }
+}
+
+/// Escape a character outside of a bracket expression.
+fn escape(c: char, text: &mut String) {
+    match c {
+        '\\' | '|' | '&' | '-' | '~' | '(' | ')' | '[' | ']' | '{' | '}' | '*' | '+' | '?'
+        | '!' | '.' | '"' | ' ' => {
+            text.push('\\');
+            text.push(c);
+        }
+        c => escape_in_class(c, text),
+    }
+}
+
+/// Escape a character inside of a bracket expression.
+fn escape_in_class(c: char, text: &mut String) {
+    match c {
+        '\\' | '[' | ']' | '^' | '-' => {
+            text.push('\\');
+            text.push(c);
+        }
+        '\n' => text.push_str("\\n"),
+        '\t' => text.push_str("\\t"),
+        '\r' => text.push_str("\\r"),
+        c if !is_graphic(c) || (c.is_whitespace() && c != ' ') => {
+            write!(text, "\\u{{{:X}}}", c as u32).unwrap();
+        }
+        c => text.push(c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::print_regex;
+    use crate::expression::Regex;
+    use crate::parse::parse_regex;
+    use crate::random::Random;
+
+    fn round_trip(pattern: &str) -> String {
+        let regex = parse_regex(pattern).unwrap();
+        let printed = print_regex(&regex);
+        assert_eq!(
+            parse_regex(&printed).unwrap(),
+            regex,
+            "Printed as {}",
+            printed
+        );
+        printed
+    }
+
+    #[test]
+    fn print_minimal_parentheses() {
+        assert_eq!(round_trip("a|bc|d*"), "a|d*|bc");
+        assert_eq!(round_trip("a|b|c*"), "[ab]|c*");
+        assert_eq!(round_trip("(ab|cd)*x"), "(ab|cd)*x");
+        assert_eq!(round_trip("a(bc)"), "abc");
+        assert_eq!(round_trip("(a|bc)|d"), "[ad]|bc");
+        assert_eq!(round_trip("ab|(cd|ef)"), "ab|cd|ef");
+        assert_eq!(round_trip("(ab)?c+"), "(ab)?c+");
+        assert_eq!(round_trip("!a*|!(b*)"), "[^a]*|!(b*)");
+        assert_eq!(round_trip("(.*-a)b"), "(.*-a)b");
+        assert_eq!(round_trip("(a*|\"\")*"), "a*");
+        assert_eq!(round_trip("a*|\"\""), "a*");
+        assert_eq!(round_trip("x+&.*y-xyz"), "x+&.*y-xyz");
+        assert_eq!(round_trip("(ab&cd)e"), "(ab&cd)e");
+        assert_eq!(round_trip("(a|b*)&!(c|d)"), "[^cd]&(a|b*)");
+        assert_eq!(round_trip("(a|b*)-(c|d)"), "(a|b*)-[cd]");
+    }
+
+    #[test]
+    fn print_escapes() {
+        assert_eq!(round_trip("\"a.b\""), "a\\.b");
+        assert_eq!(round_trip("\\ \\n\\u{0}λ"), "\\ \\n\\u{0}λ");
+        assert_eq!(round_trip("[^\\]-]"), "[^\\-\\]]");
+        assert_eq!(round_trip("[a-z\\^\\t ]"), "[\\t \\^a-z]");
+        assert_eq!(round_trip("[ab]|."), ".");
+        assert_eq!(round_trip("[^\\0-\\u{10FFFF}]"), "[^\\u{0}-\\u{10FFFF}]");
+        assert_eq!(round_trip("[\\u{E000}\\u{FFFF}]"), "[\\u{E000}\\u{FFFF}]");
+        assert_eq!(round_trip("\"\""), "\"\"");
+    }
+
+    impl Random {
+        fn char(&mut self) -> char {
+            const CHARS: &[char] = &[
+                'a',
+                'b',
+                'Z',
+                '0',
+                ' ',
+                '\n',
+                '\\',
+                '|',
+                '&',
+                '-',
+                '~',
+                '(',
+                ')',
+                '[',
+                ']',
+                '{',
+                '}',
+                '*',
+                '+',
+                '?',
+                '!',
+                '.',
+                '"',
+                '^',
+                'λ',
+                '\u{0}',
+                '\u{D7FF}',
+                '\u{E000}',
+                '\u{10FFFF}',
+            ];
+            self.choose(CHARS)
+        }
+
+        fn regex(&mut self, depth: u64) -> Regex {
+            let choice = if depth == 0 {
+                self.next(4)
+            } else {
+                self.next(13)
+            };
+            match choice {
+                0 => Regex::epsilon(),
+                1 => Regex::symbol(self.char()),
+                2 => {
+                    let (a, b) = (self.char(), self.char());
+                    Regex::symbol_range(a.min(b), a.max(b))
+                }
+                3 => match self.next(3) {
+                    0 => Regex::null(),
+                    1 => Regex::sigma(),
+                    _ => Regex::symbol_range(self.char(), std::char::MAX),
+                },
+                4 | 5 => self.regex(depth - 1) | self.regex(depth - 1),
+                6 | 7 => self.regex(depth - 1) + self.regex(depth - 1),
+                8 => self.regex(depth - 1) & self.regex(depth - 1),
+                9 => self.regex(depth - 1) - self.regex(depth - 1),
+                10 => self.regex(depth - 1).kleene(),
+                11 => self.regex(depth - 1).optional(),
+                _ => self.regex(depth - 1).logical_not(),
+            }
+        }
+    }
+
+    #[test]
+    fn print_round_trip() {
+        let mut random = Random(0x2545_F491_4F6C_DD1D);
+        for _ in 0..2000 {
+            let regex = random.regex(5);
+            let printed = print_regex(&regex);
+            match parse_regex(&printed) {
+                Ok(parsed) => assert_eq!(parsed, regex, "Printed as {}", printed),
+                Err(err) => panic!("Cannot parse {}: {}", printed, err),
+            }
+        }
+    }
+}
@@ -0,0 +1,19 @@
+//! Deterministic random numbers, for reproducible random tests.
+
+/// Xorshift generator, seeded with a nonzero state.
+pub struct Random(pub u64);
+
+impl Random {
+    /// Retrieve a number below n.
+    pub fn next(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n
+    }
+
+    /// Pick one of the given items.
+    pub fn choose<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.next(items.len() as u64) as usize]
+    }
+}
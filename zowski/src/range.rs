@@ -16,25 +16,48 @@ pub trait ItemsBetween {
     fn sub_index(&self, index: usize) -> Self;
 }
 
+/// Characters are counted as unicode scalar values, which means that
+/// the surrogate code points U+D800 to U+DFFF are skipped, and that
+/// U+D7FF and U+E000 are adjacent.
 impl ItemsBetween for char {
     fn items_between(&self, other: &Self) -> usize {
-        let a = *self as usize;
-        let b = *other as usize;
+        let a = scalar_index(*self);
+        let b = scalar_index(*other);
         assert!(b >= a);
         b + 1 - a
     }
 
     fn add_index(&self, index: usize) -> Self {
-        let a: usize = *self as usize;
-        std::char::from_u32((a + index) as u32).unwrap()
+        from_scalar_index(scalar_index(*self) + index)
     }
 
     fn sub_index(&self, index: usize) -> Self {
-        let a: usize = *self as usize;
-        std::char::from_u32((a - index) as u32).unwrap()
+        from_scalar_index(scalar_index(*self) - index)
     }
 }
 
+const SURROGATE_BEGIN: usize = 0xD800;
+const SURROGATE_COUNT: usize = 0x800;
+
+/// Position of a character, when counting only scalar values.
+fn scalar_index(c: char) -> usize {
+    let value = c as usize;
+    if value < SURROGATE_BEGIN {
+        value
+    } else {
+        value - SURROGATE_COUNT
+    }
+}
+
+fn from_scalar_index(index: usize) -> char {
+    let value = if index < SURROGATE_BEGIN {
+        index
+    } else {
+        index + SURROGATE_COUNT
+    };
+    std::char::from_u32(value as u32).expect("Character out of range")
+}
+
 impl ItemsBetween for i32 {
     fn items_between(&self, other: &Self) -> usize {
         let a = *self;
@@ -102,7 +125,26 @@ where
 
 #[cfg(test)]
 mod tests {
-    use super::Range;
+    use super::{ItemsBetween, Range};
+
+    #[test]
+    fn char_skips_surrogates() {
+        assert_eq!('\u{D7FF}'.add_index(1), '\u{E000}');
+        assert_eq!('\u{E000}'.sub_index(1), '\u{D7FF}');
+        assert_eq!('\u{D7FE}'.add_index(3), '\u{E001}');
+        assert_eq!('\u{D7FF}'.items_between(&'\u{E000}'), 2);
+        assert_eq!('A'.add_index(1), 'B');
+    }
+
+    #[test]
+    fn char_cardinality() {
+        assert_eq!(
+            Range::new('\0', std::char::MAX).cardinality(),
+            0x110000 - 0x800
+        );
+        assert_eq!(Range::new('\u{D000}', '\u{EFFF}').cardinality(), 0x1800);
+        assert_eq!(Range::new('a', 'z').cardinality(), 26);
+    }
 
     #[test]
     fn set_overlap1() {
@@ -7,7 +7,7 @@ use super::range::{ItemsBetween, Range};
 
 /// A set of integers represented internally
 /// as a sequence of ranges.
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct RangeSet<T> {
     pub ranges: Vec<Range<T>>,
 }
@@ -23,8 +23,9 @@ where
         let (first, rest) = ranges.split_first().unwrap();
         let mut range: Range<T> = first.clone();
         for r2 in rest {
-            let end_plus_one = range.end.add_index(1);
-            if r2.begin > end_plus_one {
+            // Glue when overlapping or adjacent. Note that computing
+            // end + 1 could overflow the domain.
+            if r2.begin > range.end && range.end.items_between(&r2.begin) > 2 {
                 glued_ranges.push(range);
                 range = r2.clone();
             } else {
@@ -303,6 +304,48 @@ mod tests {
         assert_eq!(s3, RangeSet::new2('A', 'K'));
     }
 
+    #[test]
+    fn set_union_at_domain_end() {
+        let s1 = RangeSet::new2('\0', std::char::MAX);
+        let s2 = RangeSet::new('A');
+        assert_eq!(s1.union(&s2), s1);
+        let s3 = RangeSet::new2('B', std::char::MAX);
+        assert_eq!(s3.union(&s2), RangeSet::new2('A', std::char::MAX));
+    }
+
+    #[test]
+    fn set_union_around_surrogates() {
+        let s1 = RangeSet::new2('a', '\u{D7FF}');
+        let s2 = RangeSet::new2('\u{E000}', '\u{F000}');
+        let s3 = s1.union(&s2);
+        assert_eq!(s3, RangeSet::new2('a', '\u{F000}'));
+        assert_eq!(s3.cardinality(), s1.cardinality() + s2.cardinality());
+    }
+
+    #[test]
+    fn set_difference_around_surrogates() {
+        let all = RangeSet::new2('\0', std::char::MAX);
+        let high = RangeSet::new2('\u{E000}', std::char::MAX);
+        let low = RangeSet::new2('\0', '\u{D7FF}');
+        assert_eq!(all.difference(&high), low);
+        assert_eq!(all.difference(&low), high);
+
+        let middle = RangeSet::new2('\u{D7FF}', '\u{E000}');
+        let rest = all.difference(&middle);
+        assert_eq!(rest.cardinality(), all.cardinality() - 2);
+        assert!(!rest.contains('\u{D7FF}'));
+        assert!(!rest.contains('\u{E000}'));
+        assert!(rest.contains('\u{D7FE}'));
+        assert!(rest.contains('\u{E001}'));
+    }
+
+    #[test]
+    fn set_iteration_around_surrogates() {
+        let s = RangeSet::new2('\u{D7FE}', '\u{E001}');
+        let items: Vec<char> = s.into_iter().collect();
+        assert_eq!(items, vec!['\u{D7FE}', '\u{D7FF}', '\u{E000}', '\u{E001}']);
+    }
+
     #[test]
     fn set_difference() {
         let s1 = RangeSet::new2('A', 'G');
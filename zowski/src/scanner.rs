@@ -1,16 +1,34 @@
 use crate::dfa::Dfa;
 use crate::expression::CharSet;
 
+// The fields are only read through the Debug output, and by tests.
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct Token {
     typ: String,
     text: String,
 }
 
+impl Token {
+    /// The token type and the scanned text.
+    #[cfg(test)]
+    pub(crate) fn parts(&self) -> (&str, &str) {
+        (&self.typ, &self.text)
+    }
+}
+
 /// Scan the given text for tokens
+///
+/// Tokens of skipped types are left out. Tokens are never empty, so
+/// a token which matches the empty string is only produced for text
+/// of at least one character.
 pub fn scan(prog: Dfa, text: &str) -> Result<Vec<Token>, String> {
-    let (transitions, accepting, error_state) =
-        (prog.transitions, prog.accepting, prog.error_state);
+    let (transitions, accepting, error_state, skipped) = (
+        prog.transitions,
+        prog.accepting,
+        prog.error_state,
+        prog.skipped,
+    );
     let mut state: usize = 0;
     let mut tokens = vec![];
 
@@ -34,10 +52,10 @@ pub fn scan(prog: Dfa, text: &str) -> Result<Vec<Token>, String> {
 
         if state == error_state {
             if let Some((tok_end, typ)) = tok_end {
-                let text: String = chars[tok_begin..tok_end].into_iter().collect();
-                let token = Token { typ, text };
-
-                tokens.push(token);
+                if !skipped.contains(&typ) {
+                    let text: String = chars[tok_begin..tok_end].iter().collect();
+                    tokens.push(Token { typ, text });
+                }
                 tok_begin = tok_end;
                 index = tok_end;
             } else {
@@ -51,9 +69,10 @@ pub fn scan(prog: Dfa, text: &str) -> Result<Vec<Token>, String> {
 
     // Add last token:
     if let Some((tok_end, typ)) = tok_end {
-        let text: String = chars[tok_begin..tok_end].into_iter().collect();
-        let token = Token { typ, text };
-        tokens.push(token);
+        if !skipped.contains(&typ) {
+            let text: String = chars[tok_begin..tok_end].iter().collect();
+            tokens.push(Token { typ, text });
+        }
     }
 
     Ok(tokens)
@@ -72,3 +91,70 @@ fn next_state(transitions: &[(usize, Vec<(CharSet, usize)>)], state: usize, c: c
 
     unreachable!("Serious trouble here. This must not happen.");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::scan;
+    use crate::{compile, ExpressionVector, Regex};
+
+    #[test]
+    fn scan_unicode() {
+        let ev = ExpressionVector::new(vec![
+            ("WORD".to_owned(), Regex::parse_or_panic("\\p{L}+")),
+            ("SPACE".to_owned(), Regex::parse_or_panic("\\s+")),
+            ("OTHER".to_owned(), Regex::parse_or_panic("[^\\p{L}\\s]")),
+        ]);
+        let dfa = compile(ev).unwrap();
+        let tokens = scan(dfa, "héllo\tλόγος €").unwrap();
+        let texts: Vec<&str> = tokens.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(texts, vec!["héllo", "\t", "λόγος", " ", "€"]);
+        assert_eq!(tokens[4].typ, "OTHER");
+    }
+
+    #[test]
+    fn scan_priority() {
+        let ev = ExpressionVector::new(vec![
+            ("ID".to_owned(), Regex::parse_or_panic("[a-z]+")),
+            ("SPACE".to_owned(), Regex::parse_or_panic("[ ]+")),
+            ("IF".to_owned(), Regex::parse_or_panic("if")),
+        ])
+        .with_priorities(vec![("IF".to_owned(), 1)]);
+        let dfa = compile(ev).unwrap();
+        let tokens = scan(dfa, "if iffy").unwrap();
+        let types: Vec<&str> = tokens.iter().map(|t| t.typ.as_str()).collect();
+        assert_eq!(types, vec!["IF", "SPACE", "ID"]);
+    }
+
+    #[test]
+    fn scan_nullable() {
+        let ev = ExpressionVector::new(vec![
+            ("NUMBER".to_owned(), Regex::parse_or_panic("[0-9]+")),
+            ("SPACE".to_owned(), Regex::parse_or_panic("[ ]*")),
+        ]);
+        let dfa = compile(ev).unwrap();
+        let tokens = scan(dfa, "1  2").unwrap();
+        let texts: Vec<&str> = tokens.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(texts, vec!["1", "  ", "2"]);
+
+        let dfa = compile(ExpressionVector::new(vec![(
+            "SPACE".to_owned(),
+            Regex::parse_or_panic("[ ]*"),
+        )]))
+        .unwrap();
+        assert!(scan(dfa, "x").is_err());
+    }
+
+    #[test]
+    fn scan_skipped() {
+        let ev = ExpressionVector::new(vec![
+            ("NUMBER".to_owned(), Regex::parse_or_panic("[0-9]+")),
+            ("SPACE".to_owned(), Regex::parse_or_panic("[ ]+")),
+            ("PLUS".to_owned(), Regex::parse_or_panic("\\+")),
+        ])
+        .with_skipped(vec!["SPACE".to_owned()]);
+        let dfa = compile(ev).unwrap();
+        let tokens = scan(dfa, " 1 +  22 ").unwrap();
+        let texts: Vec<&str> = tokens.iter().map(|t| t.text.as_str()).collect();
+        assert_eq!(texts, vec!["1", "+", "22"]);
+    }
+}
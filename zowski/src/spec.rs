@@ -1,58 +1,689 @@
-use super::parse::parse_regex;
+use super::parse::{parse_regex_with, Resolve};
 use super::Regex;
+use std::collections::HashMap;
 
 /// Read token spec from file.
 ///
 /// Simple file format, consisting of lines of:
 /// ID: PATTERN
+///
+/// Attributes can be given after the name, like `ID [skip]: PATTERN`.
+/// Tokens with the `skip` attribute are matched, but not reported.
+/// When several tokens match the same text, the one with the highest
+/// `priority=N` attribute wins, which is 0 by default.
+///
+/// Helper definitions, which are not tokens themselves, are
+/// written as `let NAME = PATTERN`, and can be used in other
+/// patterns as `{NAME}`. Lines starting with `#` are comments.
 pub fn read_spec(filename: &str) -> Result<Vec<TokenSpec>, SpecParseError> {
-    let f = std::fs::File::open(filename)?;
+    let source = std::fs::read_to_string(filename)
+        .map_err(|err| SpecParseError::Io(filename.to_owned(), err))?;
+    parse_spec(&source, filename)
+}
 
-    let mut specs = vec![];
+/// Parse token spec from text.
+///
+/// The filename is only used to report errors.
+pub fn parse_spec(source: &str, filename: &str) -> Result<Vec<TokenSpec>, SpecParseError> {
+    let mut definitions: Vec<Entry> = vec![];
+    let mut tokens: Vec<Entry> = vec![];
 
-    let reader = std::io::BufReader::new(f);
-    use std::io::BufRead;
-    for (row, line) in reader.lines().enumerate() {
-        let line: String = line?;
-        let line = line.trim();
-        if line.starts_with('#') {
+    for (row, line) in source.lines().enumerate() {
+        if line.trim().is_empty() || line.trim().starts_with('#') {
             continue;
         }
 
         // println!("Line: {}", line);
-        if line.contains(':') {
-            let mut parts = line.splitn(2, ':');
-            let name: String = parts.next().unwrap().trim().to_owned();
-            let pattern: String = parts.next().unwrap().trim().to_owned();
-            let pattern = parse_regex(pattern.as_str())
-                .map_err(|e| format!("{},{}: {}", row + 1, e.index, e.message))?;
-            // println!("ID = '{}' PATTERN = '{}'", id, pattern);
-            specs.push(TokenSpec { name, pattern });
+        let start = line.len() - line.trim_start().len();
+        if line[start..].starts_with("let ") {
+            let equals = line.find('=').ok_or_else(|| SpecError {
+                filename: filename.to_owned(),
+                line: row + 1,
+                column: line.chars().count() + 1,
+                length: 1,
+                source_line: line.to_owned(),
+                message: "Expected = in definition".to_owned(),
+                expected: vec!["=".to_owned()],
+            })?;
+            definitions.push(Entry::new(row, line, start + "let ".len(), equals));
+        } else if let Some(colon) = line.find(':') {
+            tokens.push(Entry::new(row, line, 0, colon));
+        } else {
+            return Err(SpecError {
+                filename: filename.to_owned(),
+                line: row + 1,
+                column: start + 1,
+                length: line.trim().chars().count(),
+                source_line: line.to_owned(),
+                message:
+                    "Expected a token, like NAME: PATTERN, or a definition, like let NAME = PATTERN"
+                        .to_owned(),
+                expected: vec![],
+            }
+            .into());
+        }
+    }
+
+    for definition in &definitions {
+        if let Some((text, offset)) = definition.attributes {
+            return Err(definition
+                .error(
+                    filename,
+                    offset,
+                    text.chars().count(),
+                    "A definition cannot have attributes".to_owned(),
+                )
+                .into());
         }
     }
 
+    check_names(&definitions, filename, "Definition", &[])?;
+    check_names(&tokens, filename, "Token", RESERVED_NAMES)?;
+    let definitions = resolve_definitions(&definitions, filename)?;
+
+    let mut specs = vec![];
+    for token in &tokens {
+        let pattern = token.parse(filename, &mut |name| {
+            definitions
+                .get(name)
+                .cloned()
+                .ok_or_else(|| format!("Undefined name {}", name))
+        })?;
+        if pattern.is_nullable() {
+            return Err(token
+                .error(
+                    filename,
+                    token.pattern_offset,
+                    token.pattern.chars().count(),
+                    format!(
+                        "Token {} matches the empty string, which is not allowed",
+                        token.name
+                    ),
+                )
+                .into());
+        }
+        // println!("ID = '{}' PATTERN = '{}'", id, pattern);
+        let mut skip = false;
+        let mut priority = 0;
+        for (attribute, offset) in token.attributes(filename)? {
+            let error =
+                |message: String| token.error(filename, offset, attribute.chars().count(), message);
+            match attribute.split_once('=') {
+                None if attribute == "skip" => skip = true,
+                Some((key, value)) if key.trim() == "priority" => {
+                    priority = value.trim().parse().map_err(|_| {
+                        error(format!(
+                            "Invalid priority '{}', expected a number",
+                            value.trim()
+                        ))
+                    })?;
+                }
+                _ => {
+                    return Err(error(format!(
+                        "Unknown attribute '{}', expected skip or priority=N",
+                        attribute
+                    ))
+                    .into())
+                }
+            }
+        }
+        specs.push(TokenSpec {
+            name: token.name.to_owned(),
+            pattern,
+            skip,
+            priority,
+        });
+    }
+
     Ok(specs)
 }
 
+/// Names which cannot be used for tokens, as they clash with generated code.
+const RESERVED_NAMES: &[&str] = &["ERROR"];
+
+/// Check that names are identifiers, which are valid in C code,
+/// and that they are unique.
+fn check_names(
+    entries: &[Entry],
+    filename: &str,
+    kind: &str,
+    reserved: &[&str],
+) -> Result<(), SpecError> {
+    let mut rows: HashMap<&str, usize> = HashMap::new();
+    for entry in entries {
+        let name = entry.name;
+        if !is_identifier(name) {
+            return Err(entry.name_error(
+                filename,
+                format!(
+                    "Invalid name '{}', a name must start with a letter or _, followed by letters, digits or _",
+                    name
+                ),
+            ));
+        }
+        if reserved.contains(&name) {
+            return Err(entry.name_error(filename, format!("Name {} is reserved", name)));
+        }
+        if let Some(row) = rows.insert(name, entry.row) {
+            return Err(entry.name_error(
+                filename,
+                format!("{} {} is already defined on line {}", kind, name, row + 1),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Test if a name is a C identifier, like `[A-Za-z_][A-Za-z0-9_]*`.
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+/// A `NAME: PATTERN` or `let NAME = PATTERN` line.
+struct Entry<'s> {
+    /// Line number, starting at 0.
+    row: usize,
+    line: &'s str,
+    name: &'s str,
+
+    /// Character column where the name starts, starting at 0.
+    name_offset: usize,
+
+    /// Attributes between brackets, like `[skip]`, along with
+    /// the character column where they start.
+    attributes: Option<(&'s str, usize)>,
+    pattern: &'s str,
+
+    /// Character column where the pattern starts, starting at 0.
+    pattern_offset: usize,
+}
+
+impl<'s> Entry<'s> {
+    /// Split a line into a name, starting at byte `start`,
+    /// and a pattern, after the separator at byte `separator`.
+    fn new(row: usize, line: &'s str, start: usize, separator: usize) -> Self {
+        let name_end = line[start..separator]
+            .find('[')
+            .map_or(separator, |bracket| start + bracket);
+        let name = &line[start..name_end];
+        let rest = &line[separator + 1..];
+        Entry {
+            row,
+            line,
+            name: name.trim(),
+            name_offset: line[..start].chars().count() + name.chars().count()
+                - name.trim_start().chars().count(),
+            attributes: if name_end < separator {
+                Some((
+                    line[name_end..separator].trim_end(),
+                    line[..name_end].chars().count(),
+                ))
+            } else {
+                None
+            },
+            pattern: rest.trim(),
+            pattern_offset: line[..separator + 1].chars().count() + rest.chars().count()
+                - rest.trim_start().chars().count(),
+        }
+    }
+
+    fn parse(&self, filename: &str, resolve: &mut Resolve) -> Result<Regex, SpecError> {
+        parse_regex_with(self.pattern, resolve).map_err(|e| {
+            let mut error = self.error(
+                filename,
+                self.pattern_offset + e.span.start,
+                e.span.len(),
+                e.message,
+            );
+            error.expected = e.expected;
+            error
+        })
+    }
+
+    /// Split the attributes, like `[a, b]`, into the attribute names,
+    /// along with the character column where they start.
+    fn attributes(&self, filename: &str) -> Result<Vec<(&'s str, usize)>, SpecError> {
+        let (text, offset) = match self.attributes {
+            Some(attributes) => attributes,
+            None => return Ok(vec![]),
+        };
+        let inner = text
+            .strip_prefix('[')
+            .and_then(|text| text.strip_suffix(']'))
+            .ok_or_else(|| {
+                self.error(
+                    filename,
+                    offset,
+                    text.chars().count(),
+                    "Expected attributes between brackets, like [skip]".to_owned(),
+                )
+            })?;
+
+        let mut column = offset + 1;
+        let mut attributes = vec![];
+        for part in inner.split(',') {
+            let leading = part.chars().count() - part.trim_start().chars().count();
+            attributes.push((part.trim(), column + leading));
+            column += part.chars().count() + 1;
+        }
+        Ok(attributes)
+    }
+
+    /// Create an error at the given character column, starting at 0.
+    fn error(&self, filename: &str, offset: usize, length: usize, message: String) -> SpecError {
+        SpecError {
+            filename: filename.to_owned(),
+            line: self.row + 1,
+            column: offset + 1,
+            length,
+            source_line: self.line.to_owned(),
+            message,
+            expected: vec![],
+        }
+    }
+
+    fn name_error(&self, filename: &str, message: String) -> SpecError {
+        self.error(
+            filename,
+            self.name_offset,
+            self.name.chars().count(),
+            message,
+        )
+    }
+}
+
+/// Determine the regex of each definition.
+///
+/// The names of the definitions must be unique. Definitions may refer to
+/// each other in any order, as long as they do not refer to themselves.
+fn resolve_definitions(
+    definitions: &[Entry],
+    filename: &str,
+) -> Result<HashMap<String, Regex>, SpecError> {
+    let index: HashMap<&str, usize> = definitions
+        .iter()
+        .enumerate()
+        .map(|(i, definition)| (definition.name, i))
+        .collect();
+
+    // Find references, and check the syntax of all definitions:
+    let mut references: Vec<Vec<usize>> = vec![];
+    for definition in definitions {
+        let mut referenced = vec![];
+        definition.parse(filename, &mut |name| match index.get(name) {
+            Some(i) => {
+                referenced.push(*i);
+                Ok(Regex::epsilon())
+            }
+            None => Err(format!("Undefined name {}", name)),
+        })?;
+        references.push(referenced);
+    }
+
+    let mut order = vec![];
+    let mut state = vec![Visit::New; definitions.len()];
+    for i in 0..definitions.len() {
+        if let Err(cycle) = visit(i, &references, &mut state, &mut order) {
+            let names: Vec<&str> = cycle.iter().map(|i| definitions[*i].name).collect();
+            let first = &definitions[cycle[0]];
+            return Err(first.name_error(
+                filename,
+                format!(
+                    "Definition {} refers to itself, via {}",
+                    first.name,
+                    names.join(" -> ")
+                ),
+            ));
+        }
+    }
+
+    // Resolve the definitions, such that references are resolved first.
+    let mut resolved: Vec<Option<Regex>> = vec![None; definitions.len()];
+    for i in order {
+        let regex = definitions[i].parse(filename, &mut |name| {
+            Ok(resolved[index[name]]
+                .clone()
+                .expect("References are resolved first"))
+        })?;
+        resolved[i] = Some(regex);
+    }
+
+    Ok(definitions
+        .iter()
+        .zip(resolved)
+        .map(|(definition, regex)| (definition.name.to_owned(), regex.unwrap()))
+        .collect())
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Visit {
+    New,
+    Active,
+    Done,
+}
+
+/// Depth first search, which appends definitions to `order`
+/// after the definitions they refer to.
+///
+/// Returns the cycle of definitions, when one is found.
+fn visit(
+    i: usize,
+    references: &[Vec<usize>],
+    state: &mut [Visit],
+    order: &mut Vec<usize>,
+) -> Result<(), Vec<usize>> {
+    match state[i] {
+        Visit::Done => Ok(()),
+        Visit::Active => Err(vec![i]),
+        Visit::New => {
+            state[i] = Visit::Active;
+            for referenced in &references[i] {
+                visit(*referenced, references, state, order).map_err(|mut cycle| {
+                    // Grow the cycle backwards, until it is closed.
+                    if cycle.len() == 1 || cycle[0] != *cycle.last().unwrap() {
+                        cycle.insert(0, i);
+                    }
+                    cycle
+                })?;
+            }
+            state[i] = Visit::Done;
+            order.push(i);
+            Ok(())
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum SpecParseError {
-    Io(std::io::Error),
-    Other(String),
+    /// The spec file, with the given name, cannot be read.
+    Io(String, std::io::Error),
+    Syntax(SpecError),
 }
 
-impl From<std::io::Error> for SpecParseError {
-    fn from(err: std::io::Error) -> Self {
-        Self::Io(err)
+impl From<SpecError> for SpecParseError {
+    fn from(err: SpecError) -> Self {
+        Self::Syntax(err)
     }
 }
 
-impl From<String> for SpecParseError {
-    fn from(err: String) -> Self {
-        Self::Other(err)
+impl std::fmt::Display for SpecParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Io(filename, err) => write!(f, "error: Cannot read {}: {}", filename, err),
+            Self::Syntax(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for SpecParseError {}
+
+/// An error at a certain place in a spec file.
+#[derive(Debug)]
+pub struct SpecError {
+    pub filename: String,
+
+    /// Line number, starting at 1.
+    pub line: usize,
+
+    /// Column of the first offending character, starting at 1.
+    pub column: usize,
+
+    /// Number of offending characters.
+    pub length: usize,
+
+    /// The full text of the offending line.
+    pub source_line: String,
+
+    pub message: String,
+
+    /// Descriptions of what would have been valid at this place.
+    pub expected: Vec<String>,
+}
+
+/// Render the error, along with the offending line, like this:
+///
+/// ```text
+/// error: Expected ], but got end of pattern
+///  --> pascal.zowski:3:16
+///   |
+/// 3 | STRING: '[^'\n*
+///   |                ^ expected ]
+/// ```
+impl std::fmt::Display for SpecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let gutter = " ".repeat(self.line.to_string().len());
+        writeln!(f, "error: {}", self.message)?;
+        writeln!(
+            f,
+            "{}--> {}:{}:{}",
+            gutter, self.filename, self.line, self.column
+        )?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", self.line, self.source_line)?;
+
+        // Keep tabs, such that the markers line up with the source line.
+        let indent: String = self
+            .source_line
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        write!(
+            f,
+            "{} | {}{}",
+            gutter,
+            indent,
+            "^".repeat(self.length.max(1))
+        )?;
+        if !self.expected.is_empty() {
+            write!(f, " expected {}", self.expected.join(" or "))?;
+        }
+        Ok(())
     }
 }
 
 pub struct TokenSpec {
     pub name: String,
     pub pattern: Regex,
+
+    /// Whether this token is matched, but not reported.
+    pub skip: bool,
+
+    /// Priority over other tokens matching the same text,
+    /// where the highest priority wins.
+    pub priority: i32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_spec, read_spec, SpecError, SpecParseError};
+    use crate::Regex;
+
+    #[test]
+    fn spec_with_comments() {
+        let specs = parse_spec("# Numbers\nNUMBER: [0-9]+\n\nID: [a-z]+\n", "test.zowski").unwrap();
+        let names: Vec<&str> = specs.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["NUMBER", "ID"]);
+    }
+
+    #[test]
+    fn spec_unreadable() {
+        match read_spec("missing.zowski") {
+            Err(err @ SpecParseError::Io(..)) => {
+                assert!(err
+                    .to_string()
+                    .starts_with("error: Cannot read missing.zowski: "))
+            }
+            Err(err) => panic!("Expected an I/O error, got {}", err),
+            Ok(_) => panic!("Expected an I/O error"),
+        }
+    }
+
+    #[test]
+    fn spec_error_position() {
+        let source = "NUMBER: [0-9]+\nSTRING:  '[^'\\n*\n";
+        match parse_spec(source, "test.zowski") {
+            Err(SpecParseError::Syntax(err)) => {
+                assert_eq!(err.line, 2);
+                assert_eq!(err.column, 17);
+                assert_eq!(err.expected, vec!["]"]);
+                assert_eq!(
+                    err.to_string(),
+                    "error: Expected ], but got end of pattern\n \
+                     --> test.zowski:2:17\n  \
+                     |\n\
+                     2 | STRING:  '[^'\\n*\n  \
+                     |                 ^ expected ]"
+                );
+            }
+            _ => panic!("Expected a syntax error"),
+        }
+    }
+
+    #[test]
+    fn spec_with_definitions() {
+        let source = "\
+            let ident = {letter}({letter}|{digit})*\n\
+            let letter = [a-z]\n\
+            let digit = [0-9]\n\
+            ID: {ident}\n\
+            NUMBER: {digit}+\n";
+        let specs = parse_spec(source, "test.zowski").unwrap();
+        let names: Vec<&str> = specs.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["ID", "NUMBER"]);
+        assert!(specs[0].pattern.matches("a1b"));
+        assert!(!specs[0].pattern.matches("1b"));
+        assert_eq!(specs[1].pattern, Regex::parse_or_panic("[0-9]+"));
+    }
+
+    fn syntax_error(source: &str) -> SpecError {
+        match parse_spec(source, "test.zowski") {
+            Err(SpecParseError::Syntax(err)) => err,
+            _ => panic!("Expected a syntax error"),
+        }
+    }
+
+    #[test]
+    fn spec_undefined_name() {
+        let err = syntax_error("let digit = [0-9]\nNUMBER: {digit}+|{hex}\n");
+        assert_eq!(err.message, "Undefined name hex");
+        assert_eq!((err.line, err.column, err.length), (2, 18, 5));
+
+        let err = syntax_error("let a = {b}\n");
+        assert_eq!(err.message, "Undefined name b");
+        assert_eq!((err.line, err.column), (1, 9));
+    }
+
+    #[test]
+    fn spec_definition_errors() {
+        let err = syntax_error("let a = x{b}\nlet b = {c}y\nlet c = {a}|z\n");
+        assert_eq!(
+            err.message,
+            "Definition a refers to itself, via a -> b -> c -> a"
+        );
+        assert_eq!((err.line, err.column, err.length), (1, 5, 1));
+
+        let err = syntax_error("let a = a\nlet  a = b\n");
+        assert_eq!(err.message, "Definition a is already defined on line 1");
+        assert_eq!((err.line, err.column), (2, 6));
+
+        let err = syntax_error("let a = [0-9]\nlet b = {a}(\n");
+        assert_eq!((err.line, err.column), (2, 13));
+
+        let err = syntax_error("let a [0-9]\n");
+        assert_eq!(err.message, "Expected = in definition");
+    }
+
+    #[test]
+    fn spec_invalid_names() {
+        let err = syntax_error("A: a\nB: b\n A : c\n");
+        assert_eq!(err.message, "Token A is already defined on line 1");
+        assert_eq!((err.line, err.column, err.length), (3, 2, 1));
+
+        let err = syntax_error("ERROR: [^a]\n");
+        assert_eq!(err.message, "Name ERROR is reserved");
+
+        let err = syntax_error("NUMBER: [0-9]+\n2ND: x\n");
+        assert_eq!((err.line, err.column, err.length), (2, 1, 3));
+        assert!(syntax_error("MY TOKEN: x\n")
+            .message
+            .starts_with("Invalid name 'MY TOKEN'"));
+        assert!(syntax_error(": x\n").message.starts_with("Invalid name ''"));
+        assert!(syntax_error("let a-b = x\n")
+            .message
+            .starts_with("Invalid name 'a-b'"));
+
+        // The same name can be used for a token and a definition.
+        assert!(parse_spec("let ID = [a-z]+\nID: {ID}\n", "test.zowski").is_ok());
+    }
+
+    #[test]
+    fn spec_attributes() {
+        let source = "SPACE [skip]: [ ]+\nCOMMENT [ skip ] : #.*\nID: [a-z]+\n";
+        let specs = parse_spec(source, "test.zowski").unwrap();
+        let names: Vec<&str> = specs.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["SPACE", "COMMENT", "ID"]);
+        let skip: Vec<bool> = specs.iter().map(|s| s.skip).collect();
+        assert_eq!(skip, vec![true, true, false]);
+
+        let err = syntax_error("A [skip, foo]: a\n");
+        assert_eq!(
+            err.message,
+            "Unknown attribute 'foo', expected skip or priority=N"
+        );
+        assert_eq!((err.column, err.length), (10, 3));
+
+        let err = syntax_error("A [skip: a\n");
+        assert_eq!((err.column, err.length), (3, 5));
+
+        let specs = parse_spec("A [priority=2, skip]: a\nB [priority = -1]: b\n", "t").unwrap();
+        assert_eq!((specs[0].priority, specs[0].skip), (2, true));
+        assert_eq!((specs[1].priority, specs[1].skip), (-1, false));
+
+        let err = syntax_error("A [priority=high]: a\n");
+        assert_eq!(err.message, "Invalid priority 'high', expected a number");
+        assert_eq!((err.column, err.length), (4, 13));
+
+        let err = syntax_error("let a [skip] = a\n");
+        assert_eq!(err.message, "A definition cannot have attributes");
+    }
+
+    #[test]
+    fn spec_nullable_token() {
+        let err = syntax_error("NUMBER: [0-9]+\nWHITESPACE:  [ ]*\n");
+        assert_eq!(
+            err.message,
+            "Token WHITESPACE matches the empty string, which is not allowed"
+        );
+        assert_eq!((err.line, err.column, err.length), (2, 14, 4));
+
+        // Definitions can be nullable, as long as tokens are not.
+        assert!(parse_spec("let digits = [0-9]*\nNUMBER: [0-9]{digits}\n", "t").is_ok());
+    }
+
+    #[test]
+    fn spec_garbage_line() {
+        let err = syntax_error("A: a\n\n   \n  B = b\n");
+        assert_eq!((err.line, err.column, err.length), (4, 3, 5));
+        assert!(err.message.starts_with("Expected a token"));
+    }
+
+    #[test]
+    fn spec_error_span() {
+        let source = "X: a{3,1}\n";
+        match parse_spec(source, "test.zowski") {
+            Err(SpecParseError::Syntax(err)) => {
+                assert_eq!(err.column, 5);
+                assert_eq!(err.length, 5);
+                assert!(err.to_string().ends_with("\n  |     ^^^^^"));
+            }
+            _ => panic!("Expected a syntax error"),
+        }
+    }
 }
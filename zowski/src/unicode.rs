@@ -0,0 +1,227 @@
+//! Lookup of unicode properties, as used by `\p{...}` in patterns.
+//!
+//! Supported are general categories (`\p{L}`, `\p{Nd}`,
+//! `\p{General_Category=Letter}`), scripts (`\p{Greek}`, `\p{sc=Grek}`,
+//! `\p{Script=Greek}`) and a few binary properties (`\p{XID_Start}`).
+//!
+//! Names are matched loosely, ignoring case, spaces, `-` and `_`.
+//!
+//! Also provides simple case folding, as used by `(?i:...)`.
+
+use crate::expression::{sigma, CharSet};
+use crate::range::Range;
+use crate::unicode_tables::{
+    Table, BINARY_PROPERTIES, GENERAL_CATEGORIES, SCRIPTS, SIMPLE_CASE_FOLDING,
+};
+
+/// General category groups, which combine several categories.
+const CATEGORY_GROUPS: &[(&str, &str, &[&str])] = &[
+    ("L", "Letter", &["Lu", "Ll", "Lt", "Lm", "Lo"]),
+    ("LC", "Cased_Letter", &["Lu", "Ll", "Lt"]),
+    ("M", "Mark", &["Mn", "Mc", "Me"]),
+    ("N", "Number", &["Nd", "Nl", "No"]),
+    (
+        "P",
+        "Punctuation",
+        &["Pc", "Pd", "Ps", "Pe", "Pi", "Pf", "Po"],
+    ),
+    ("S", "Symbol", &["Sm", "Sc", "Sk", "So"]),
+    ("Z", "Separator", &["Zs", "Zl", "Zp"]),
+    ("C", "Other", &["Cc", "Cf", "Co", "Cn"]),
+];
+
+/// Look up the set of characters with the given property.
+///
+/// The property is either a name, like `Lu` or `Greek`, or a
+/// `key=value` pair, like `Script=Greek`.
+pub fn property_class(property: &str) -> Option<CharSet> {
+    let mut parts = property.splitn(2, ['=', ':']);
+    let first = parts.next().unwrap();
+    if let Some(value) = parts.next() {
+        match loose(first).as_str() {
+            "gc" | "generalcategory" => general_category(value),
+            "sc" | "script" => lookup(SCRIPTS, value),
+            _ => None,
+        }
+    } else {
+        match loose(first).as_str() {
+            "any" => Some(sigma()),
+            "ascii" => Some(CharSet::new2('\0', '\u{7F}')),
+            _ => general_category(first)
+                .or_else(|| lookup(BINARY_PROPERTIES, first))
+                .or_else(|| lookup(SCRIPTS, first)),
+        }
+    }
+}
+
+/// Extend a set with all characters that are equal to one of its
+/// characters under simple case folding.
+///
+/// For example, `k` gives `k`, `K` and the Kelvin sign `\u{212A}`.
+pub fn case_fold_closure(set: &CharSet) -> CharSet {
+    // Characters which fold to the same character are equal, so first
+    // find the folded forms in use, and then all characters folding to them.
+    let folded: Vec<Range<char>> = SIMPLE_CASE_FOLDING
+        .iter()
+        .filter(|(c, f)| set.contains(*c) || set.contains(*f))
+        .map(|(_, f)| Range::new(*f, *f))
+        .collect();
+    if folded.is_empty() {
+        return set.clone();
+    }
+
+    let folded = CharSet::from_ranges(folded);
+    let equal = SIMPLE_CASE_FOLDING
+        .iter()
+        .filter(|(_, f)| folded.contains(*f))
+        .map(|(c, _)| Range::new(*c, *c))
+        .collect();
+    set.union(&folded).union(&CharSet::from_ranges(equal))
+}
+
+/// Test if a character is visible when printed on its own.
+///
+/// Control and format characters, separators other than spaces,
+/// private use characters and unassigned code points, which include
+/// the noncharacters, are not graphic.
+pub fn is_graphic(c: char) -> bool {
+    const NOT_GRAPHIC: &[&str] = &["Cc", "Cf", "Co", "Cn", "Zl", "Zp"];
+    !GENERAL_CATEGORIES
+        .iter()
+        .filter(|(short, _, _)| NOT_GRAPHIC.contains(short))
+        .any(|(_, _, table)| in_table(table, c))
+}
+
+/// Test if a character is in one of the sorted ranges of a table.
+fn in_table(table: Table, c: char) -> bool {
+    table
+        .binary_search_by(|(begin, end)| {
+            if *end < c {
+                std::cmp::Ordering::Less
+            } else if *begin > c {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        })
+        .is_ok()
+}
+
+fn general_category(name: &str) -> Option<CharSet> {
+    if let Some(set) = lookup(GENERAL_CATEGORIES, name) {
+        Some(set)
+    } else {
+        let (_, _, members) = CATEGORY_GROUPS
+            .iter()
+            .find(|(short, long, _)| matches_name(name, short, long))?;
+        let sets = members
+            .iter()
+            .map(|member| lookup(GENERAL_CATEGORIES, member).unwrap());
+        Some(sets.fold(CharSet::empty(), |a, b| a.union(&b)))
+    }
+}
+
+fn lookup(tables: &[(&str, &str, Table)], name: &str) -> Option<CharSet> {
+    let (_, _, table) = tables
+        .iter()
+        .find(|(short, long, _)| matches_name(name, short, long))?;
+    let ranges = table.iter().map(|r| Range::new(r.0, r.1)).collect();
+    Some(CharSet::from_ranges(ranges))
+}
+
+fn matches_name(name: &str, short: &str, long: &str) -> bool {
+    let name = loose(name);
+    name == loose(short) || name == loose(long)
+}
+
+/// Normalize a name for loose matching.
+fn loose(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '_'))
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{case_fold_closure, is_graphic, property_class};
+    use crate::expression::CharSet;
+
+    #[test]
+    fn general_category() {
+        let lu = property_class("Lu").unwrap();
+        assert!(lu.contains('A'));
+        assert!(lu.contains('Ω'));
+        assert!(!lu.contains('a'));
+        assert_eq!(property_class("Uppercase_Letter"), Some(lu.clone()));
+        assert_eq!(property_class("gc=lu"), Some(lu));
+    }
+
+    #[test]
+    fn category_group() {
+        let letters = property_class("L").unwrap();
+        assert!(letters.contains('a'));
+        assert!(letters.contains('Z'));
+        assert!(letters.contains('λ'));
+        assert!(letters.contains('字'));
+        assert!(!letters.contains('1'));
+        assert_eq!(property_class("Letter"), Some(letters));
+    }
+
+    #[test]
+    fn script() {
+        let greek = property_class("Script=Greek").unwrap();
+        assert!(greek.contains('λ'));
+        assert!(!greek.contains('a'));
+        assert_eq!(property_class("sc=Grek"), Some(greek.clone()));
+        assert_eq!(property_class("greek"), Some(greek));
+    }
+
+    #[test]
+    fn binary_property() {
+        let start = property_class("XID_Start").unwrap();
+        let cont = property_class("XID_Continue").unwrap();
+        assert!(start.contains('a'));
+        assert!(!start.contains('1'));
+        assert!(cont.contains('1'));
+        assert!(cont.contains('_'));
+        assert!(!cont.contains('-'));
+    }
+
+    #[test]
+    fn unknown_property() {
+        assert_eq!(property_class("Foo"), None);
+        assert_eq!(property_class("Script=Lu"), None);
+        assert_eq!(property_class("Block=Basic_Latin"), None);
+    }
+
+    #[test]
+    fn graphic() {
+        assert!(is_graphic('a'));
+        assert!(is_graphic(' '));
+        assert!(is_graphic('€'));
+        assert!(!is_graphic('\n'));
+        assert!(!is_graphic('\u{AD}'));
+        assert!(!is_graphic('\u{2028}'));
+        assert!(!is_graphic('\u{E000}'));
+        assert!(!is_graphic('\u{378}'));
+        assert!(!is_graphic('\u{FFFF}'));
+        assert!(!is_graphic(std::char::MAX));
+    }
+
+    #[test]
+    fn case_folding() {
+        let k = case_fold_closure(&CharSet::new('k'));
+        assert_eq!(k.cardinality(), 3);
+        assert!(k.contains('K'));
+        assert!(k.contains('\u{212A}'));
+        assert_eq!(case_fold_closure(&CharSet::new('\u{212A}')), k);
+
+        let sigma = case_fold_closure(&CharSet::new('Σ'));
+        assert!(sigma.contains('σ'));
+        assert!(sigma.contains('ς'));
+
+        let digits = CharSet::new2('0', '9');
+        assert_eq!(case_fold_closure(&digits), digits);
+    }
+}
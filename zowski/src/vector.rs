@@ -1,63 +1,87 @@
-use crate::expression::{product_intersections, CharSet, Regex};
+use crate::expression::Regex;
+use crate::spec::TokenSpec;
 
 /// A vector of regular expressions.
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ExpressionVector {
     expressions: Vec<(String, Regex)>,
+
+    /// Names of the expressions which are matched, but not reported.
+    skipped: Vec<String>,
+
+    /// Priorities of expressions, which are 0 when not given.
+    priorities: Vec<(String, i32)>,
 }
 
 impl ExpressionVector {
     pub fn new(expressions: Vec<(String, Regex)>) -> Self {
-        ExpressionVector { expressions }
+        ExpressionVector {
+            expressions,
+            skipped: vec![],
+            priorities: vec![],
+        }
     }
 
-    /// Retrieve names of the expressions
-    pub fn names(&self) -> Vec<String> {
-        self.expressions.iter().map(|e| e.0.clone()).collect()
+    /// Create an expression vector from the tokens of a spec file, with
+    /// their skip attributes and priorities.
+    pub fn from_specs(specs: Vec<TokenSpec>) -> Self {
+        let mut expressions = vec![];
+        let mut skipped = vec![];
+        let mut priorities = vec![];
+        for spec in specs {
+            if spec.skip {
+                skipped.push(spec.name.clone());
+            }
+            if spec.priority != 0 {
+                priorities.push((spec.name.clone(), spec.priority));
+            }
+            expressions.push((spec.name, spec.pattern));
+        }
+        ExpressionVector::new(expressions)
+            .with_skipped(skipped)
+            .with_priorities(priorities)
     }
 
-    /// Contrapt the derivative of this expression vector with
-    /// respect to the given character.
-    pub fn derivative(&self, c: char) -> ExpressionVector {
-        let e2 = self
-            .expressions
-            .iter()
-            .map(|(n, e)| (n.to_owned(), e.derivative(c)))
-            .collect();
-        ExpressionVector::new(e2)
+    /// Mark the given expressions as skipped, such that matches of them
+    /// are not reported by the scanners.
+    pub fn with_skipped(mut self, skipped: Vec<String>) -> Self {
+        self.skipped = skipped;
+        self
     }
 
-    /// Retrieve which patterns are matched by this state.
-    pub fn is_nullable(&self) -> Vec<String> {
-        self.expressions
-            .iter()
-            .filter(|e| e.1.is_nullable())
-            .map(|e| e.0.to_owned())
-            .collect()
+    /// Retrieve names of the skipped expressions
+    pub fn skipped(&self) -> &[String] {
+        &self.skipped
     }
 
-    /// Test if all patterns are null
-    pub fn is_null(&self) -> bool {
-        self.expressions.iter().all(|e| e.1.is_null())
+    /// Set the priorities of expressions. When several expressions
+    /// match, the one with the highest priority wins.
+    pub fn with_priorities(mut self, priorities: Vec<(String, i32)>) -> Self {
+        self.priorities = priorities;
+        self
     }
 
-    /// Retrieve the different character classes that might
-    /// introduce different behavior.
-    pub fn character_classes(&self) -> Vec<CharSet> {
-        let x: Vec<Vec<CharSet>> = self
-            .expressions
+    /// Retrieve the priorities which are set.
+    pub fn priorities(&self) -> &[(String, i32)] {
+        &self.priorities
+    }
+
+    /// Retrieve the priority of the expression with the given name.
+    pub fn priority(&self, name: &str) -> i32 {
+        self.priorities
             .iter()
-            .map(|e| e.1.character_classes())
-            .collect();
-        // .fold();
-        if x.is_empty() {
-            vec![]
-        } else {
-            // println!("X={}", x.len());
-            let (first, rest) = x.split_first().unwrap();
-            rest.iter()
-                .fold(first.clone(), |a, b| product_intersections(a, b.clone()))
-        }
+            .find(|(n, _)| n == name)
+            .map_or(0, |(_, priority)| *priority)
+    }
+
+    /// Retrieve the expressions, with their names.
+    pub(crate) fn expressions(&self) -> &[(String, Regex)] {
+        &self.expressions
+    }
+
+    /// Retrieve names of the expressions
+    pub fn names(&self) -> Vec<String> {
+        self.expressions.iter().map(|e| e.0.clone()).collect()
     }
 }
 
@@ -0,0 +1,124 @@
+#!/usr/bin/env perl
+#
+# Generate the unicode property tables used by `\p{...}` patterns,
+# and the case folding table used by `(?i:...)`.
+#
+# The tables are taken from the Unicode Character Database shipped
+# with perl. Usage, from the zowski crate directory:
+#
+#     $ perl tools/gen_unicode_tables.pl > src/unicode_tables.rs
+
+use strict;
+use warnings;
+use Unicode::UCD qw(prop_invlist prop_values prop_value_aliases);
+
+my @binary_properties = (
+    ["Alpha", "Alphabetic"],
+    ["Lower", "Lowercase"],
+    ["Upper", "Uppercase"],
+    ["WSpace", "White_Space"],
+    ["IDS", "ID_Start"],
+    ["IDC", "ID_Continue"],
+    ["XIDS", "XID_Start"],
+    ["XIDC", "XID_Continue"],
+);
+
+# Convert an inversion list into inclusive ranges of scalar values.
+#
+# Surrogates are not scalar values, so ranges are clipped to exclude them,
+# and ranges on both sides of the surrogate block are joined.
+sub ranges {
+    my @invlist = @_;
+    push @invlist, 0x110000 if @invlist % 2;
+    my @ranges;
+    while (my ($begin, $end) = splice(@invlist, 0, 2)) {
+        $end -= 1;
+        $begin = 0xE000 if $begin >= 0xD800 && $begin <= 0xDFFF;
+        $end = 0xD7FF if $end >= 0xD800 && $end <= 0xDFFF;
+        next if $begin > $end;
+        if (@ranges && $ranges[-1][1] == 0xD7FF && $begin == 0xE000) {
+            $ranges[-1][1] = $end;
+        } else {
+            push @ranges, [$begin, $end];
+        }
+    }
+    return @ranges;
+}
+
+sub const_name {
+    my ($prefix, $name) = @_;
+    return uc("${prefix}_$name");
+}
+
+my @tables;
+
+sub table {
+    my ($const, $property) = @_;
+    my @ranges = ranges(prop_invlist($property));
+    my @items = map { sprintf("('\\u{%x}', '\\u{%x}')", @$_) } @ranges;
+    my $text = "const $const: Table = &[\n";
+    while (my @line = splice(@items, 0, 4)) {
+        $text .= "    " . join(", ", @line) . ",\n";
+    }
+    $text .= "];\n";
+    push @tables, $text;
+}
+
+my $version = Unicode::UCD::UnicodeVersion();
+print <<"HEADER";
+// ATTENTION: automatically generated code, do not edit by hand
+// Generated by tools/gen_unicode_tables.pl from Unicode $version
+
+//! Unicode character property tables.
+
+pub type Table = &'static [(char, char)];
+
+HEADER
+
+print "/// General categories, by short and long name.\n";
+print "pub const GENERAL_CATEGORIES: &[(&str, &str, Table)] = &[\n";
+for my $gc (prop_values("General_Category")) {
+    my ($short, $long) = prop_value_aliases("gc", $gc);
+    # Skip the groups, such as L, and the surrogates.
+    next if length($short) != 2 || $short eq "LC" || $short eq "Cs";
+    my $const = const_name("GC", $short);
+    print "    (\"$short\", \"$long\", $const),\n";
+    table($const, "gc=$short");
+}
+print "];\n\n";
+
+print "/// Scripts, by short and long name.\n";
+print "pub const SCRIPTS: &[(&str, &str, Table)] = &[\n";
+for my $sc (sort(prop_values("Script"))) {
+    my ($short, $long) = prop_value_aliases("sc", $sc);
+    # Katakana_Or_Hiragana is only used by Script_Extensions.
+    next unless defined $short;
+    my $const = const_name("SC", $short);
+    print "    (\"$short\", \"$long\", $const),\n";
+    table($const, "sc=$short");
+}
+print "];\n\n";
+
+print "/// Binary properties, by short and long name.\n";
+print "pub const BINARY_PROPERTIES: &[(&str, &str, Table)] = &[\n";
+for my $property (@binary_properties) {
+    my ($short, $long) = @$property;
+    my $const = const_name("BP", $short);
+    print "    (\"$short\", \"$long\", $const),\n";
+    table($const, $long);
+}
+print "];\n\n";
+
+# Only the simple (one to one) foldings are used, as a character set
+# cannot express folding into multiple characters.
+my $casefolds = Unicode::UCD::all_casefolds();
+print "/// Simple case folding, as pairs of a character and its folded form.\n";
+print "pub const SIMPLE_CASE_FOLDING: &[(char, char)] = &[\n";
+for my $code (sort { $a <=> $b } keys %$casefolds) {
+    my $fold = $casefolds->{$code};
+    next unless $fold->{status} eq "C" || $fold->{status} eq "S";
+    printf("    ('\\u{%x}', '\\u{%x}'),\n", $code, hex($fold->{simple}));
+}
+print "];\n";
+
+print "\n", join("\n", @tables);